@@ -0,0 +1,171 @@
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::Config;
+use crate::{generate_bot, Bot};
+
+/// Rechenberg's factor for the 1/5 success rule
+const STEP_FACTOR: f64 = 0.85;
+const TARGET_SUCCESS_RATE: f64 = 0.2;
+
+/// Tracks whether the champion still improves, adapts the mutation strength
+/// to it and tells when the run has stagnated.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Adaptation {
+    /// Average number of mutations applied to a new child
+    pub mutation_strength: f64,
+    /// Best champion benchmark win rate since the start or the last restart
+    best_quality: Option<f64>,
+    /// Improvements in the current window
+    successes: usize,
+    /// Iterations in the current window
+    observed: usize,
+    pub stagnant_iterations: usize,
+    /// Best champion benchmark win rate of the whole run, kept by restarts
+    run_best_quality: Option<f64>,
+    /// Iterations without a better champion in the whole run, which
+    /// `stagnation_limit` looks at. Unlike `stagnant_iterations` restarts
+    /// don't reset it, or a restart would keep the run going forever.
+    pub run_stagnant_iterations: usize,
+}
+
+impl Default for Adaptation {
+    fn default() -> Self {
+        Adaptation {
+            mutation_strength: 1.0,
+            best_quality: None,
+            successes: 0,
+            observed: 0,
+            stagnant_iterations: 0,
+            run_best_quality: None,
+            run_stagnant_iterations: 0,
+        }
+    }
+}
+
+impl Adaptation {
+    /// Records the champion's mean benchmark win rate of an iteration. At the
+    /// end of every `adaptation_window` iterations the 1/5 success rule
+    /// applies: the mutation strength grows if the champion improved in more
+    /// than a fifth of them and shrinks if it improved in fewer.
+    pub fn record(&mut self, quality: f64, config: &Config) {
+        if self.best_quality.is_none_or(|best| quality > best) {
+            self.best_quality = Some(quality);
+            self.successes += 1;
+            self.stagnant_iterations = 0;
+        } else {
+            self.stagnant_iterations += 1;
+        }
+        self.observed += 1;
+        if self.run_best_quality.is_none_or(|best| quality > best) {
+            self.run_best_quality = Some(quality);
+            self.run_stagnant_iterations = 0;
+        } else {
+            self.run_stagnant_iterations += 1;
+        }
+
+        if config.adaptive_mutation && self.observed >= config.adaptation_window {
+            let success_rate = self.successes as f64 / self.observed as f64;
+            if success_rate > TARGET_SUCCESS_RATE {
+                self.mutation_strength /= STEP_FACTOR;
+            } else if success_rate < TARGET_SUCCESS_RATE {
+                self.mutation_strength *= STEP_FACTOR;
+            }
+            self.mutation_strength = self
+                .mutation_strength
+                .clamp(1.0, config.max_mutation_strength);
+            self.successes = 0;
+            self.observed = 0;
+        }
+    }
+
+    pub fn stagnated(&self, config: &Config) -> bool {
+        config.stagnation_restart > 0 && self.stagnant_iterations >= config.stagnation_restart
+    }
+
+    /// Starts over measuring improvement, the restarted population is not
+    /// comparable to the old best.
+    pub fn restarted(&mut self) {
+        *self = Adaptation {
+            run_best_quality: self.run_best_quality,
+            run_stagnant_iterations: self.run_stagnant_iterations,
+            ..Adaptation::default()
+        };
+    }
+
+    /// Mutations for one child: the strength rounded up or down at random
+    /// so the average matches it.
+    pub fn mutations<RNG: rand::Rng>(&self, rng: &mut RNG) -> usize {
+        let whole = self.mutation_strength.floor();
+        let fraction = self.mutation_strength - whole;
+        whole as usize + usize::from(fraction > 0.0 && rng.gen_bool(fraction))
+    }
+}
+
+/// Replaces every bot of the `count` weakest species with a new random bot
+/// in its own species. The species of the best bot, `bots[0]`, is always
+/// kept. Returns the number of replaced bots.
+pub fn restart_weakest_species<RNG: rand::Rng>(
+    bots: &mut [Bot],
+    count: usize,
+    config: &Config,
+    rng: &mut RNG,
+) -> usize {
+    let mut species_ranks = BTreeMap::new();
+    for bot in bots.iter() {
+        let rank = bot.rank(config.cull_by);
+        match species_ranks.get_mut(&bot.species) {
+            Some(best) if rank > *best => *best = rank,
+            Some(_) => {}
+            None => {
+                species_ranks.insert(bot.species, rank);
+            }
+        }
+    }
+    species_ranks.remove(&bots[0].species);
+
+    let mut weakest = species_ranks.into_iter().collect::<Vec<_>>();
+    weakest.sort_by(|(_, a), (_, b)| a.cmp(b));
+    weakest.truncate(count);
+
+    let mut replaced = 0;
+    for bot in bots.iter_mut() {
+        if weakest.iter().any(|(species, _)| *species == bot.species) {
+            *bot = Bot {
+                island: bot.island,
+                ..generate_bot(rng)
+            };
+            replaced += 1;
+        }
+    }
+    replaced
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn restarts_do_not_hide_stagnation_from_the_limit() {
+        let config = Config {
+            stagnation_restart: 3,
+            stagnation_limit: Some(5),
+            ..Config::default()
+        };
+        let mut adaptation = Adaptation::default();
+
+        adaptation.record(0.5, &config);
+        for _ in 0..10 {
+            adaptation.record(0.5, &config);
+            if adaptation.stagnated(&config) {
+                adaptation.restarted();
+            }
+            assert!(adaptation.stagnant_iterations < config.stagnation_restart);
+        }
+        assert_eq!(adaptation.run_stagnant_iterations, 10);
+
+        adaptation.record(0.75, &config);
+        assert_eq!(adaptation.run_stagnant_iterations, 0);
+    }
+}
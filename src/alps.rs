@@ -0,0 +1,156 @@
+use crate::{generate_bot, Bot};
+
+/// Highest age allowed in `layer` of `layers`, spaced polynomially as
+/// 1, 2, 4, 9, 16, ... times `age_gap`. The top layer has no limit.
+pub fn age_limit(layer: usize, layers: usize, age_gap: usize) -> Option<usize> {
+    if layer + 1 >= layers {
+        return None;
+    }
+
+    let factor = match layer {
+        0 => 1,
+        1 => 2,
+        layer => layer * layer,
+    };
+    Some(age_gap * factor)
+}
+
+/// Moves every bot that has outgrown its layer up one layer, see `move_up`.
+/// The layer it leaves shrinks until mutation refills it from the bots that
+/// stayed, only a layer left empty starts over with random bots. Goes from
+/// the top down so a bot only moves one layer per iteration. Expects every
+/// layer sorted best first. Returns the number of promoted bots.
+pub fn promote<RNG: rand::Rng>(layers: &mut [Vec<Bot>], age_gap: usize, rng: &mut RNG) -> usize {
+    let count = layers.len();
+    let mut promoted = 0;
+
+    for layer in (0..count.saturating_sub(1)).rev() {
+        let limit = age_limit(layer, count, age_gap).unwrap();
+        let size = layers[layer].len();
+        let (too_old, young): (Vec<_>, Vec<_>) = std::mem::take(&mut layers[layer])
+            .into_iter()
+            .partition(|bot| bot.age >= limit);
+        layers[layer] = young;
+        promoted += move_up(layers, layer, too_old);
+
+        // random bots are not evaluated yet, culling would rank them against
+        // evaluated ones, so they only fill a layer nobody stayed in
+        if layers[layer].is_empty() {
+            layers[layer] = random_layer(layer, size, rng);
+        }
+    }
+    promoted
+}
+
+/// Moves `bots` from `layer` to the next one, where they replace its worst
+/// bots. Never pushes out more than half of that layer, the rest is too old
+/// to stay anywhere and dropped. Returns the number of bots moved.
+fn move_up(layers: &mut [Vec<Bot>], layer: usize, bots: Vec<Bot>) -> usize {
+    let Some(upper) = layers.get_mut(layer + 1) else {
+        return 0;
+    };
+    let moving = bots.into_iter().take(upper.len() / 2).collect::<Vec<_>>();
+    let moved = moving.len();
+    upper.truncate(upper.len() - moved);
+    upper.extend(moving.into_iter().map(|bot| Bot {
+        island: layer + 1,
+        ..bot
+    }));
+    moved
+}
+
+fn random_layer<RNG: rand::Rng>(layer: usize, size: usize, rng: &mut RNG) -> Vec<Bot> {
+    (0..size)
+        .map(|_| Bot {
+            island: layer,
+            ..generate_bot(rng)
+        })
+        .collect()
+}
+
+/// Replaces the bottom layer with new random bots. The layer is renewed
+/// every `age_gap` iterations, the same age its bots would be promoted at,
+/// so the old bots move up to the next layer instead of being lost. Returns
+/// the number of bots moved up.
+pub fn renew_bottom_layer<RNG: rand::Rng>(layers: &mut [Vec<Bot>], rng: &mut RNG) -> usize {
+    let old = std::mem::take(&mut layers[0]);
+    let size = old.len();
+    let moved = move_up(layers, 0, old);
+    layers[0] = random_layer(0, size, rng);
+    moved
+}
+
+/// Every bot grows a generation older, children already carry the age of
+/// their parent.
+pub fn grow_older(layers: &mut [Vec<Bot>]) {
+    for bot in layers.iter_mut().flatten() {
+        bot.age += 1;
+    }
+}
+
+/// Ages in every layer, for the console
+pub fn report(layers: &[Vec<Bot>], age_gap: usize) {
+    for (layer, bots) in layers.iter().enumerate() {
+        let min = bots.iter().map(|bot| bot.age).min().unwrap_or(0);
+        let max = bots.iter().map(|bot| bot.age).max().unwrap_or(0);
+        match age_limit(layer, layers.len(), age_gap) {
+            Some(limit) => println!("\tLayer {layer}:\t ages {min}-{max}, below {limit}"),
+            None => println!("\tLayer {layer}:\t ages {min}-{max}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::{Expression, ExpressionKind};
+    use crate::family_tree::Species;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    /// `count` layers of `size` bots, every bot's species is its layer
+    fn layers(count: usize, size: usize) -> Vec<Vec<Bot>> {
+        (0..count)
+            .map(|layer| {
+                (0..size)
+                    .map(|_| Bot {
+                        species: Species(layer as u64),
+                        island: layer,
+                        ..Bot::from_logic(Expression::new(ExpressionKind::X))
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn age_limits_grow_polynomially() {
+        let limits = (0..5).map(|layer| age_limit(layer, 5, 3)).collect::<Vec<_>>();
+        assert_eq!(limits, [Some(3), Some(6), Some(12), Some(27), None]);
+    }
+
+    #[test]
+    fn renewed_bots_reach_the_next_layer() {
+        let age_gap = 3;
+        // the top layer has no age limit, anything else in it came from below
+        let mut layers = layers(2, 6);
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        // the order of `train`, without evaluation, culling and mutation
+        let mut promoted = 0;
+        for iteration in 0..4 * age_gap {
+            promoted += promote(&mut layers, age_gap, &mut rng);
+            grow_older(&mut layers);
+            if (iteration + 1) % age_gap == 0 {
+                promoted += renew_bottom_layer(&mut layers, &mut rng);
+            }
+        }
+
+        assert!(promoted > 0);
+        assert!(layers[1].iter().any(|bot| bot.species != Species(1)));
+        for (layer, bots) in layers.iter().enumerate() {
+            assert_eq!(bots.len(), 6);
+            assert!(bots.iter().all(|bot| bot.island == layer));
+        }
+    }
+}
@@ -0,0 +1,322 @@
+use std::collections::BTreeMap;
+
+use futures::future::join_all;
+use serde::{Deserialize, Serialize};
+use logic::{ActionType, Coords, ObjDetails, RobotRunner, Team, Unit};
+
+use crate::config::Config;
+use crate::fitness::GAME_LENGTH;
+use crate::metrics::append_lines;
+use crate::expression::{Expression, ExpressionKind, Move};
+use crate::logic_ext::{CoordsExt, Direction, TeamExt};
+
+/// Hand written opponents that never change, so the champion's results
+/// against them can be compared between iterations and between runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reference {
+    /// The `Species(0)` bot every population starts with
+    EastWest,
+    /// Walks to the nearest enemy and attacks it
+    Rusher,
+    /// Walks to the center and attacks whatever comes close
+    CenterCamper,
+    /// Port of the target selection in `robot_template.py`
+    Template,
+}
+
+impl Reference {
+    pub const ALL: [Reference; 4] = [
+        Reference::EastWest,
+        Reference::Rusher,
+        Reference::CenterCamper,
+        Reference::Template,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Reference::EastWest => "east_west",
+            Reference::Rusher => "rusher",
+            Reference::CenterCamper => "center_camper",
+            Reference::Template => "template",
+        }
+    }
+
+    fn runner(self) -> Runner {
+        match self {
+            Reference::EastWest => Runner::Genome(east_west()),
+            Reference::Rusher => Runner::Genome(rusher()),
+            Reference::CenterCamper => Runner::Genome(center_camper()),
+            Reference::Template => Runner::Template,
+        }
+    }
+}
+
+fn if_then(condition: Box<Expression>, then: Box<Expression>, otherwise: Box<Expression>) -> Expression {
+    Expression::new(ExpressionKind::If {
+        condition,
+        then,
+        otherwise,
+    })
+}
+
+fn greater_than(left: ExpressionKind, right: ExpressionKind) -> Box<Expression> {
+    Expression::new_box(ExpressionKind::GreaterThan {
+        left: Expression::new_box(left),
+        right: Expression::new_box(right),
+    })
+}
+
+fn step(direction: Direction) -> Box<Expression> {
+    Expression::new_box(ExpressionKind::ConstantMove(Move::Move(direction)))
+}
+
+fn enemy_adjacent() -> Box<Expression> {
+    greater_than(
+        ExpressionKind::EnemySurroundingTiles,
+        ExpressionKind::ConstantNumber(0),
+    )
+}
+
+/// Moves west on the east half of the map and east on the west half.
+pub fn east_west() -> Expression {
+    if_then(
+        greater_than(ExpressionKind::X, ExpressionKind::ConstantNumber(9)),
+        step(Direction::West),
+        step(Direction::East),
+    )
+}
+
+fn rusher() -> Expression {
+    if_then(
+        enemy_adjacent(),
+        Expression::new_box(ExpressionKind::AttackNearestEnemy),
+        Expression::new_box(ExpressionKind::MoveToNearestEnemy),
+    )
+}
+
+fn center_camper() -> Expression {
+    let center = || ExpressionKind::ConstantNumber(9);
+    let at_center = if_then(
+        greater_than(ExpressionKind::Y, center()),
+        step(Direction::North),
+        Box::new(if_then(
+            greater_than(center(), ExpressionKind::Y),
+            step(Direction::South),
+            Expression::new_box(ExpressionKind::AttackNearestEnemy),
+        )),
+    );
+    let towards_center = if_then(
+        greater_than(ExpressionKind::X, center()),
+        step(Direction::West),
+        Box::new(if_then(
+            greater_than(center(), ExpressionKind::X),
+            step(Direction::East),
+            Box::new(at_center),
+        )),
+    );
+
+    if_then(
+        enemy_adjacent(),
+        Expression::new_box(ExpressionKind::AttackNearestEnemy),
+        Box::new(towards_center),
+    )
+}
+
+/// Either side of a benchmark game. `logic::run` wants a single runner type
+/// for both teams.
+enum Runner {
+    Genome(Expression),
+    Template,
+}
+
+/// `CoordsExt::distance` mixes up the axes, which the evolved genomes are
+/// used to, but the reference has to measure the map correctly.
+fn manhattan(a: Coords, b: Coords) -> usize {
+    a.0.abs_diff(b.0) + a.1.abs_diff(b.1)
+}
+
+/// The template picks the closest enemy, preferring ones surrounded by
+/// allies and low on health, and fills in the move with the genome. The
+/// port attacks that enemy when it is adjacent and walks towards it otherwise.
+fn template_action(input: &logic::ProgramInput, coords: Coords) -> Option<logic::Action> {
+    let is_ally = |tile: Coords| {
+        input
+            .state
+            .grid
+            .get(&tile)
+            .and_then(|id| input.state.objs.get(id))
+            .is_some_and(|obj| match &obj.1 {
+                ObjDetails::Unit(Unit { team, .. }) => *team == input.team,
+                _ => false,
+            })
+    };
+    let allies_around = |tile: Coords| {
+        [(0, 1), (0, -1), (-1, 0), (1, 0), (1, 1), (1, -1), (-1, 1), (-1, -1)]
+            .into_iter()
+            .filter(|&(dx, dy)| {
+                is_ally(Coords(
+                    tile.0.wrapping_add_signed(dx),
+                    tile.1.wrapping_add_signed(dy),
+                ))
+            })
+            .count()
+    };
+
+    let target = input
+        .state
+        .teams
+        .get(&input.team.opposite())?
+        .iter()
+        .flat_map(|id| input.state.objs.get(id))
+        .min_by_key(|enemy| {
+            let health = match &enemy.1 {
+                ObjDetails::Unit(unit) => unit.health,
+                _ => 0,
+            };
+            (
+                manhattan(enemy.coords(), coords),
+                usize::MAX - allies_around(enemy.coords()),
+                health,
+            )
+        })?;
+
+    let type_ = if manhattan(target.coords(), coords) <= 1 {
+        ActionType::Attack
+    } else {
+        ActionType::Move
+    };
+    Some(logic::Action {
+        type_,
+        direction: coords.direction(target.coords()).into(),
+    })
+}
+
+#[async_trait::async_trait]
+impl RobotRunner for Runner {
+    async fn run(&mut self, input: logic::ProgramInput<'_>) -> logic::ProgramResult {
+        match self {
+            Runner::Genome(genome) => genome.run(input).await,
+            Runner::Template => {
+                let robot_actions = input
+                    .state
+                    .teams
+                    .get(&input.team)
+                    .unwrap()
+                    .iter()
+                    .map(|&id| {
+                        let coords = input.state.objs.get(&id).unwrap().coords();
+                        (id, Ok(template_action(&input, coords)))
+                    })
+                    .collect();
+
+                Ok(logic::ProgramOutput {
+                    robot_actions,
+                    logs: vec![],
+                    debug_inspect_tables: BTreeMap::new(),
+                    debug_locate_queries: vec![],
+                })
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct BenchmarkResult {
+    pub wins: usize,
+    pub draws: usize,
+    pub losses: usize,
+}
+
+impl BenchmarkResult {
+    /// Draws count as half a win
+    pub fn win_rate(&self) -> f64 {
+        let games = self.wins + self.draws + self.losses;
+        if games == 0 {
+            return 0.0;
+        }
+        (self.wins as f64 + self.draws as f64 / 2.0) / games as f64
+    }
+}
+
+/// Plays `games` games of `champion` against every reference opponent,
+/// alternating colours. The seeds only depend on the master seed, so every
+/// measurement of a run plays the same maps.
+pub async fn benchmark(
+    champion: &Expression,
+    games: usize,
+    master_seed: u64,
+) -> Vec<(Reference, BenchmarkResult)> {
+    let mut handles = vec![];
+    for (reference_index, reference) in Reference::ALL.into_iter().enumerate() {
+        for game in 0..games {
+            let champion_team = if game % 2 == 0 { Team::Blue } else { Team::Red };
+            let mut runners = BTreeMap::new();
+            runners.insert(champion_team, Ok(Runner::Genome(champion.canonical())));
+            runners.insert(champion_team.opposite(), Ok(reference.runner()));
+            let seed = format!("{master_seed:016x}{reference_index:016x}{game:016x}");
+
+            handles.push(tokio::task::spawn_blocking(move || {
+                let output = futures::executor::block_on(logic::run(
+                    runners,
+                    |_| (),
+                    GAME_LENGTH,
+                    true,
+                    None,
+                    logic::GameMode::Normal,
+                    Some(seed),
+                ));
+                (reference_index, champion_team, output.winner)
+            }));
+        }
+    }
+
+    let mut results = Reference::ALL.map(|reference| (reference, BenchmarkResult::default()));
+    for finished in join_all(handles).await {
+        let (reference_index, champion_team, winner) = finished.expect("game panicked");
+        let result = &mut results[reference_index].1;
+        match winner {
+            None => result.draws += 1,
+            Some(team) if team == champion_team => result.wins += 1,
+            Some(_) => result.losses += 1,
+        }
+    }
+    results.to_vec()
+}
+
+/// Win rate averaged over the reference opponents
+pub fn mean_win_rate(results: &[(Reference, BenchmarkResult)]) -> f64 {
+    let total = results.iter().map(|(_, result)| result.win_rate()).sum::<f64>();
+    total / results.len().max(1) as f64
+}
+
+/// Win rate against every reference opponent, on one line
+pub fn summary(results: &[(Reference, BenchmarkResult)]) -> String {
+    results
+        .iter()
+        .map(|(reference, result)| format!("{} {:.2}", reference.name(), result.win_rate()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Prints the results and appends them to `<save file>.benchmark.csv`, one
+/// row per reference opponent.
+pub fn record(config: &Config, iteration: usize, results: &[(Reference, BenchmarkResult)]) {
+    println!("\tBenchmark:\t {}", summary(results));
+
+    let rows = results.iter().map(|(reference, result)| {
+        format!(
+            "{iteration},{},{},{},{},{:.4}",
+            reference.name(),
+            result.wins,
+            result.draws,
+            result.losses,
+            result.win_rate()
+        )
+    });
+    append_lines(
+        &config.run_file("benchmark.csv"),
+        Some("iteration,opponent,wins,draws,losses,win_rate"),
+        rows,
+    );
+}
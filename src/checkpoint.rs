@@ -0,0 +1,205 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::adaptation::Adaptation;
+use crate::config::Config;
+use crate::family_tree::FamilyTree;
+use crate::hall_of_fame::HallOfFame;
+use crate::map_elites::EliteArchive;
+use crate::novelty::NoveltyArchive;
+use crate::rating::SpeciesRatings;
+use crate::Bot;
+
+/// Bumped whenever the layout of `Checkpoint` changes, older layouts are
+/// migrated in `SaveFile::migrate`.
+pub const FORMAT_VERSION: u32 = 1;
+
+/// Everything needed to continue a run exactly where it stopped.
+#[derive(Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub format_version: u32,
+    /// The next iteration to run
+    pub iteration: usize,
+    pub seed: u64,
+    pub config: Config,
+    pub rng: ChaCha8Rng,
+    pub family_tree: FamilyTree,
+    pub hall_of_fame: HallOfFame,
+    pub species_ratings: SpeciesRatings,
+    /// Champions of every island, used by `coevolution`
+    pub population_champions: Vec<HallOfFame>,
+    pub novelty_archive: NoveltyArchive,
+    pub elites: EliteArchive,
+    pub adaptation: Adaptation,
+    /// Games played in the run so far
+    pub games_played: usize,
+    pub bots: Vec<Bot>,
+}
+
+enum SaveFile {
+    Versioned(Box<Checkpoint>),
+    /// Version 0, only the population
+    Population(Vec<Bot>),
+}
+
+impl SaveFile {
+    /// Picks the layout by `format_version`, so a checkpoint that does not
+    /// match the layout of its version is an error instead of being read as
+    /// an older version that ignores most of it.
+    fn parse(value: ciborium::Value) -> Result<SaveFile, String> {
+        fn layout<T: serde::de::DeserializeOwned>(
+            value: &ciborium::Value,
+            version: &str,
+        ) -> Result<T, String> {
+            value
+                .deserialized()
+                .map_err(|e| format!("not a valid version {version} checkpoint: {e}"))
+        }
+
+        let Some(fields) = value.as_map() else {
+            return layout(&value, "0").map(SaveFile::Population);
+        };
+        let version = fields
+            .iter()
+            .find(|(key, _)| key.as_text() == Some("format_version"))
+            .ok_or_else(|| "checkpoint has no format_version".to_string())?
+            .1
+            .as_integer()
+            .and_then(|version| u32::try_from(version).ok())
+            .ok_or_else(|| "format_version is not a version number".to_string())?;
+
+        if version > FORMAT_VERSION {
+            return Err(format!(
+                "checkpoint has format version {version}, this build only reads up to {FORMAT_VERSION}"
+            ));
+        }
+        layout(&value, &version.to_string())
+            .map(|checkpoint| SaveFile::Versioned(Box::new(checkpoint)))
+    }
+
+    fn migrate(self, config: &Config) -> Result<Checkpoint, String> {
+        let checkpoint = match self {
+            SaveFile::Versioned(checkpoint) => *checkpoint,
+            SaveFile::Population(bots) => {
+                let seed = config.seed.unwrap_or_else(|| rand::thread_rng().gen());
+                Checkpoint {
+                    format_version: FORMAT_VERSION,
+                    iteration: 0,
+                    seed,
+                    config: config.clone(),
+                    rng: ChaCha8Rng::seed_from_u64(seed),
+                    family_tree: FamilyTree::from_population(&bots),
+                    hall_of_fame: HallOfFame::default(),
+                    species_ratings: SpeciesRatings::default(),
+                    population_champions: vec![],
+                    novelty_archive: NoveltyArchive::default(),
+                    elites: EliteArchive::default(),
+                    adaptation: Adaptation::default(),
+                    games_played: 0,
+                    bots,
+                }
+            }
+        };
+
+        Ok(Checkpoint {
+            format_version: FORMAT_VERSION,
+            ..checkpoint
+        })
+    }
+}
+
+/// Name of the `generation`th older checkpoint, `0` is the save file itself.
+fn rotated_path(save_file: &Path, generation: usize) -> PathBuf {
+    if generation == 0 {
+        return save_file.to_path_buf();
+    }
+
+    let mut name = save_file.as_os_str().to_owned();
+    name.push(format!(".{generation}"));
+    PathBuf::from(name)
+}
+
+impl Checkpoint {
+    /// Reads a single checkpoint file, migrating older formats.
+    pub fn read(path: &Path, config: &Config) -> Result<Checkpoint, String> {
+        let file = OpenOptions::new()
+            .read(true)
+            .open(path)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        let value = ciborium::from_reader(file).map_err(|e| format!("{}: {e}", path.display()))?;
+        let save = SaveFile::parse(value).map_err(|e| format!("{}: {e}", path.display()))?;
+        save.migrate(config)
+    }
+
+    /// Loads the newest readable checkpoint of `config.save_file`, falling
+    /// back to older ones if it is missing or damaged. `None` if there is no
+    /// checkpoint at all.
+    pub fn load_latest(config: &Config) -> Option<Checkpoint> {
+        let save_file = Path::new(&config.save_file);
+
+        for generation in 0..=config.checkpoints_to_keep {
+            let path = rotated_path(save_file, generation);
+            if !path.exists() {
+                continue;
+            }
+
+            match Checkpoint::read(&path, config) {
+                Ok(checkpoint) => {
+                    if generation > 0 {
+                        eprintln!("loaded older checkpoint {}", path.display());
+                    }
+                    return Some(checkpoint);
+                }
+                Err(e) => eprintln!("skipping unreadable checkpoint: {e}"),
+            }
+        }
+
+        None
+    }
+
+    /// Writes the checkpoint atomically, see `write_atomically`. The previous
+    /// `checkpoints_to_keep` saves are kept as `<save file>.1`, `.2`, ...
+    pub fn save(&self) -> std::io::Result<()> {
+        write_atomically(
+            Path::new(&self.config.save_file),
+            self,
+            self.config.checkpoints_to_keep,
+        )
+    }
+}
+
+/// Serialises `value` to a temporary file and renames it over `path`, so a
+/// crash never leaves a half written file behind. With `keep` above 0 the
+/// previous versions are rotated to `<path>.1` up to `<path>.<keep>` first.
+pub fn write_atomically<T: Serialize>(path: &Path, value: &T, keep: usize) -> std::io::Result<()> {
+    let mut temporary = path.as_os_str().to_owned();
+    temporary.push(".tmp");
+    let temporary = PathBuf::from(temporary);
+
+    let mut file = OpenOptions::new()
+        .write(true)
+        .truncate(true)
+        .create(true)
+        .open(&temporary)?;
+    ciborium::into_writer(value, &mut file).map_err(std::io::Error::other)?;
+    file.flush()?;
+    file.sync_all()?;
+    drop(file);
+
+    if keep > 0 {
+        let _ = std::fs::remove_file(rotated_path(path, keep));
+        for generation in (0..keep).rev() {
+            let from = rotated_path(path, generation);
+            if from.exists() {
+                std::fs::rename(from, rotated_path(path, generation + 1))?;
+            }
+        }
+    }
+
+    std::fs::rename(&temporary, path)
+}
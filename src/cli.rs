@@ -0,0 +1,150 @@
+use std::path::PathBuf;
+
+use crate::config::{Config, ConfigError};
+
+pub const USAGE: &str = "usage:
+    genetic_robot [train] [--option value]...
+    genetic_robot play <bot> <bot> [--option value]...
+    genetic_robot export <bot> [--format python|js|json] [--option value]...
+    genetic_robot export-elites <directory> [--format python|js|json] [--option value]...
+    genetic_robot inspect <save file>
+
+<bot> is either the rank of a bot in the save file, as listed by inspect, or a
+JSON file containing a genome. Options are the fields of the configuration,
+see `Config`.";
+
+#[derive(Debug, Clone, Copy)]
+pub enum ExportFormat {
+    Python,
+    Js,
+    Json,
+}
+
+pub enum Command {
+    Train(Config),
+    Play {
+        config: Config,
+        blue: String,
+        red: String,
+    },
+    Export {
+        config: Config,
+        bot: String,
+        format: ExportFormat,
+    },
+    Inspect {
+        save: PathBuf,
+    },
+    ExportElites {
+        config: Config,
+        directory: PathBuf,
+        format: ExportFormat,
+    },
+}
+
+impl Command {
+    /// Parses the command line, not including the program name. Without a
+    /// subcommand the arguments are passed to `train`.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Command, ConfigError> {
+        let mut args = args.into_iter().collect::<Vec<_>>();
+        let subcommand = match args.first() {
+            Some(first) if !first.starts_with("--") => args.remove(0),
+            _ => "train".to_string(),
+        };
+
+        let (positional, mut flags) = split_arguments(args)?;
+        let expect_positional = |count: usize| {
+            if positional.len() == count {
+                Ok(())
+            } else {
+                Err(ConfigError::Usage(format!(
+                    "{subcommand} expects {count} argument(s), got {}",
+                    positional.len()
+                )))
+            }
+        };
+
+        match subcommand.as_str() {
+            "train" => {
+                expect_positional(0)?;
+                Ok(Command::Train(Config::from_args(flags)?))
+            }
+            "play" => {
+                expect_positional(2)?;
+                let mut positional = positional.into_iter();
+                Ok(Command::Play {
+                    config: Config::from_args(flags)?,
+                    blue: positional.next().unwrap(),
+                    red: positional.next().unwrap(),
+                })
+            }
+            "export" => {
+                expect_positional(1)?;
+                let format = take_format(&mut flags)?;
+                Ok(Command::Export {
+                    config: Config::from_args(flags)?,
+                    bot: positional.into_iter().next().unwrap(),
+                    format,
+                })
+            }
+            "inspect" => {
+                expect_positional(1)?;
+                if let Some(flag) = flags.first() {
+                    return Err(ConfigError::UnexpectedArgument(flag.clone()));
+                }
+                Ok(Command::Inspect {
+                    save: PathBuf::from(positional.into_iter().next().unwrap()),
+                })
+            }
+            "export-elites" => {
+                expect_positional(1)?;
+                let format = take_format(&mut flags)?;
+                Ok(Command::ExportElites {
+                    config: Config::from_args(flags)?,
+                    directory: PathBuf::from(positional.into_iter().next().unwrap()),
+                    format,
+                })
+            }
+            other => Err(ConfigError::Usage(format!("unknown command {other:?}"))),
+        }
+    }
+}
+
+/// Separates positional arguments from `--flag value` pairs.
+fn split_arguments(args: Vec<String>) -> Result<(Vec<String>, Vec<String>), ConfigError> {
+    let mut positional = vec![];
+    let mut flags = vec![];
+
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg.starts_with("--") {
+            let value = args
+                .next()
+                .ok_or_else(|| ConfigError::MissingValue(arg.clone()))?;
+            flags.push(arg);
+            flags.push(value);
+        } else {
+            positional.push(arg);
+        }
+    }
+
+    Ok((positional, flags))
+}
+
+/// Removes `--format` and its value, Python if it is missing.
+fn take_format(flags: &mut Vec<String>) -> Result<ExportFormat, ConfigError> {
+    match take_flag(flags, "--format").as_deref() {
+        None | Some("python") => Ok(ExportFormat::Python),
+        Some("js") => Ok(ExportFormat::Js),
+        Some("json") => Ok(ExportFormat::Json),
+        Some(other) => Err(ConfigError::Usage(format!("unknown export format {other:?}"))),
+    }
+}
+
+/// Removes `flag` and its value from a list of `--flag value` pairs.
+fn take_flag(flags: &mut Vec<String>, flag: &str) -> Option<String> {
+    let index = flags.chunks(2).position(|pair| pair[0] == flag)? * 2;
+    let value = flags.remove(index + 1);
+    flags.remove(index);
+    Some(value)
+}
@@ -0,0 +1,132 @@
+use logic::Team;
+use rand::seq::SliceRandom;
+
+use crate::benchmark::BenchmarkResult;
+use crate::evaluation::{play_matches, Player};
+use crate::fitness::GameMetrics;
+use crate::game_cache::GameCache;
+use crate::hall_of_fame::HallOfFame;
+use crate::rating;
+use crate::tournament::{add_hall_of_fame_wins, sort_by_fitness, Evaluation};
+use crate::Bot;
+
+/// Copies of the bots of every population but `population`, the opponents
+/// its bots are measured against.
+pub fn opponents(islands: &[Vec<Bot>], population: usize) -> Vec<Bot> {
+    islands
+        .iter()
+        .enumerate()
+        .filter(|&(index, _)| index != population)
+        .flat_map(|(_, bots)| bots.iter().cloned())
+        .collect()
+}
+
+/// Every bot plays `games` opponents sampled from the other populations as
+/// blue, and with `symmetric_games` the same games again as red. Only the
+/// bots' scores and ratings change, the opponents just measure them. Leaves
+/// `bots` sorted from worst to best like a `Tournament`.
+pub async fn play_opponents(
+    bots: &mut [Bot],
+    opponents: &[Bot],
+    games: usize,
+    evaluation: &mut Evaluation<'_>,
+) {
+    let mut matches = vec![];
+    let mut bot_teams = vec![];
+    for index in 0..bots.len() {
+        for opponent in opponents.choose_multiple(&mut evaluation.rng, games) {
+            matches.push((Player::Bot(index), Player::Genome(&opponent.logic)));
+            bot_teams.push((index, Team::Blue, opponent));
+            if evaluation.config.symmetric_games {
+                matches.push((Player::Genome(&opponent.logic), Player::Bot(index)));
+                bot_teams.push((index, Team::Red, opponent));
+            }
+        }
+    }
+
+    let results = play_matches(bots, &matches, &mut evaluation.cache, evaluation.seed).await;
+    let k_factor = evaluation.config.elo_k_factor;
+
+    for ((index, team, opponent), result) in bot_teams.into_iter().zip(results) {
+        evaluation.side_bias.record(result.winner);
+        let bot = &mut bots[index];
+        // the opponent's rating is only a reference point, its own
+        // population keeps track of it
+        let mut opponent_rating = opponent.rating;
+        let (blue_species, red_species) = match team {
+            Team::Blue => {
+                rating::update(
+                    &mut bot.rating,
+                    &mut opponent_rating,
+                    result.winner,
+                    k_factor,
+                );
+                (bot.species, opponent.species)
+            }
+            Team::Red => {
+                rating::update(
+                    &mut opponent_rating,
+                    &mut bot.rating,
+                    result.winner,
+                    k_factor,
+                );
+                (opponent.species, bot.species)
+            }
+        };
+        evaluation
+            .species_games
+            .record(blue_species, red_species, result.winner);
+
+        let metrics = GameMetrics::new(&result, team);
+        bot.score.wins[0] += metrics.points();
+        bot.score.add_game(&metrics, &evaluation.config.fitness);
+    }
+
+    add_hall_of_fame_wins(bots);
+    sort_by_fitness(bots);
+}
+
+/// Plays every population's current champion against all past champions of
+/// the other populations, once with each colour. A rising win rate means the
+/// populations are making progress instead of cycling through strategies.
+pub async fn play_champion_histories(
+    champions: &[Bot],
+    histories: &[HallOfFame],
+    cache: &mut GameCache,
+    master_seed: u64,
+) -> Vec<BenchmarkResult> {
+    let mut results = vec![];
+    for (population, champion) in champions.iter().enumerate() {
+        let mut matches = vec![];
+        let mut teams = vec![];
+        let others = histories
+            .iter()
+            .enumerate()
+            .filter(|&(index, _)| index != population)
+            .flat_map(|(_, history)| history.champions());
+        for other in others {
+            matches.push((Player::Bot(0), Player::Genome(&other.logic)));
+            teams.push(Team::Blue);
+            matches.push((Player::Genome(&other.logic), Player::Bot(0)));
+            teams.push(Team::Red);
+        }
+
+        // played on a copy so measuring does not change the usage counters
+        let mut champion = [champion.clone()];
+        let mut session = cache.session();
+        let summaries = play_matches(&mut champion, &matches, &mut session, master_seed).await;
+        let updates = session.finish();
+        cache.merge(updates);
+
+        let mut result = BenchmarkResult::default();
+        for (team, summary) in teams.into_iter().zip(summaries) {
+            match summary.winner {
+                Some(winner) if winner == team => result.wins += 1,
+                Some(_) => result.losses += 1,
+                None => result.draws += 1,
+            }
+        }
+        results.push(result);
+    }
+    results
+}
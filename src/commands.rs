@@ -0,0 +1,140 @@
+use std::cmp::Reverse;
+use std::path::Path;
+
+use rand::Rng;
+
+use crate::cli::ExportFormat;
+use crate::config::{Config, CullKey};
+use crate::expression::Expression;
+use crate::game_cache::{GameKey, GameSummary};
+use crate::checkpoint::Checkpoint;
+use crate::{draw_game, run_game, Bot};
+
+const PYTHON_TEMPLATE: &str = include_str!("robot_template.py");
+const JS_TEMPLATE: &str = include_str!("robot_template.js");
+
+/// Population sorted from the best to the worst, by the last evaluation's
+/// score or by rating depending on `cull_by`.
+fn ranked(mut bots: Vec<Bot>, cull_by: CullKey) -> Vec<Bot> {
+    bots.sort_by_key(|bot| Reverse(bot.rank(cull_by)));
+    bots
+}
+
+/// Finds a bot by its rank in the save file, or loads a genome from a JSON file.
+fn resolve_bot(config: &Config, reference: &str) -> Result<Bot, String> {
+    if let Ok(rank) = reference.parse::<usize>() {
+        let checkpoint = Checkpoint::read(Path::new(&config.save_file), config)?;
+        let bots = ranked(checkpoint.bots, checkpoint.config.cull_by);
+        let length = bots.len();
+        return bots
+            .into_iter()
+            .nth(rank)
+            .ok_or_else(|| format!("there are only {length} bots in {}", config.save_file));
+    }
+
+    let file = std::fs::File::open(reference).map_err(|e| format!("{reference}: {e}"))?;
+    let logic: Expression =
+        serde_json::from_reader(file).map_err(|e| format!("{reference}: {e}"))?;
+    Ok(Bot::from_logic(logic))
+}
+
+pub async fn play(config: &Config, blue: &str, red: &str) -> Result<(), String> {
+    let mut bots = vec![resolve_bot(config, blue)?, resolve_bot(config, red)?];
+    let seed = config.seed.unwrap_or_else(|| rand::thread_rng().gen());
+    let game_seed = GameKey::new(&bots[0].logic, &bots[1].logic, seed).seed;
+
+    let result = run_game(&mut bots, 0, 1, Some(game_seed)).await;
+
+    for (turn, frame) in draw_game(&result).iter().enumerate() {
+        println!("Turn {turn}");
+        print!("{frame}");
+    }
+
+    let summary = GameSummary::from_output(&result);
+    match summary.winner {
+        Some(team) => println!("Winner: {team:?}"),
+        None => println!("Draw"),
+    }
+    println!(
+        "Blue: {} units, {} health\nRed: {} units, {} health\nSeed: {seed}",
+        summary.blue_units, summary.blue_health, summary.red_units, summary.red_health
+    );
+    Ok(())
+}
+
+pub fn export_bot(bot: &Bot, format: ExportFormat) -> String {
+    match format {
+        ExportFormat::Python => PYTHON_TEMPLATE.replacen("{}", &bot.logic.to_string(), 1),
+        ExportFormat::Js => JS_TEMPLATE.replacen("{}", &bot.logic.to_js().to_string(), 1),
+        ExportFormat::Json => serde_json::to_string_pretty(&bot.logic).unwrap(),
+    }
+}
+
+pub fn export(config: &Config, bot: &str, format: ExportFormat) -> Result<(), String> {
+    let bot = resolve_bot(config, bot)?;
+    println!("{}", export_bot(&bot, format));
+    Ok(())
+}
+
+pub fn export_elites(config: &Config, directory: &Path, format: ExportFormat) -> Result<(), String> {
+    let checkpoint = Checkpoint::read(Path::new(&config.save_file), config)?;
+    if checkpoint.elites.is_empty() {
+        return Err(format!(
+            "{} has no elites, train with --map-elites true first",
+            config.save_file
+        ));
+    }
+
+    checkpoint
+        .elites
+        .export(directory, format)
+        .map_err(|e| format!("{}: {e}", directory.display()))?;
+    println!(
+        "exported {} elites to {}",
+        checkpoint.elites.len(),
+        directory.display()
+    );
+    Ok(())
+}
+
+pub fn inspect(save: &Path) -> Result<(), String> {
+    let checkpoint = Checkpoint::read(save, &Config::default())?;
+    println!(
+        "format version {}, next iteration {}, seed {}, {} champions in the hall of fame",
+        checkpoint.format_version,
+        checkpoint.iteration,
+        checkpoint.seed,
+        checkpoint.hall_of_fame.len()
+    );
+
+    let bots = ranked(checkpoint.bots, checkpoint.config.cull_by);
+
+    println!("rank\tspecies\tgeneration\tsize\thash\trating\tscore");
+    for (rank, bot) in bots.iter().enumerate() {
+        println!(
+            "{rank}\t{}\t{}\t{}\t{:016x}\t{}\t{:?}",
+            bot.species,
+            bot.generation,
+            bot.logic.size(),
+            bot.logic.structural_hash(),
+            bot.rating,
+            bot.score
+        );
+    }
+
+    if checkpoint.hall_of_fame.len() > 0 {
+        println!();
+        println!("iteration\tspecies\tsize\thash\tscore");
+        for champion in checkpoint.hall_of_fame.champions() {
+            println!(
+                "{}\t{}\t{}\t{:016x}\t{:?}",
+                champion.iteration,
+                champion.species,
+                champion.logic.size(),
+                champion.hash,
+                champion.score
+            );
+        }
+    }
+    Ok(())
+}
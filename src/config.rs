@@ -0,0 +1,483 @@
+use std::fs::OpenOptions;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::benchmark::Reference;
+use crate::fitness::FitnessWeights;
+use crate::selection::Selection;
+
+/// What `cull_bots` ranks bots and species by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CullKey {
+    /// This iteration's playoff results
+    Score,
+    /// The Elo rating carried across iterations
+    Rating,
+    /// NSGA-II: Pareto front of the game metrics, then crowding distance
+    Pareto,
+    /// Mix of how different a bot plays and its score, see `novelty`
+    Novelty,
+}
+
+/// How the population is paired up each iteration, see `tournament`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TournamentKind {
+    Playoff,
+    RoundRobin,
+    Swiss,
+    RandomOpponents,
+}
+
+/// Which islands receive an island's migrants
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MigrationTopology {
+    /// The next island, the last one sends to the first
+    Ring,
+    /// Every other island
+    FullyConnected,
+    /// One other island, picked anew at every migration
+    Random,
+}
+
+/// A value of `novelty::Behaviour` that MAP-Elites bins bots by
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BehaviourDimension {
+    CenterDistance,
+    Spread,
+    Engagement,
+    FirstContact,
+}
+
+/// File format of the per iteration metrics log, see `metrics`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MetricsFormat {
+    Off,
+    Csv,
+    Jsonl,
+}
+
+/// Training hyperparameters. Read from an optional JSON file given with
+/// `--config`, then every field can be overridden on the command line with
+/// `--field-name value`, e.g. `--num-robots 100 --save-file run2.cbor`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Bots per island
+    pub num_robots: usize,
+    pub surviving_robots: usize,
+    pub num_species: usize,
+    pub crossover_interval: usize,
+    pub min_bots_per_species: usize,
+    /// Populations that evolve separately and only exchange migrants, the
+    /// population settings above apply to each of them
+    pub islands: usize,
+    /// Iterations between migrations, 0 turns migration off
+    pub migration_interval: usize,
+    /// Best bots every island sends to each of its destinations
+    pub migrants: usize,
+    pub migration_topology: MigrationTopology,
+    /// Islands compete instead of exchanging migrants: bots are measured
+    /// against bots of the other islands rather than their own
+    pub coevolution: bool,
+    /// Opponents from the other islands every bot plays with `coevolution`
+    pub coevolution_games: usize,
+    /// The islands are age layers instead, the youngest first: bots move up
+    /// when they get too old for their layer and the bottom layer is
+    /// regularly replaced with random bots, see `alps`
+    pub alps: bool,
+    /// Iterations between renewals of the bottom layer, and the unit of the
+    /// layers' age limits
+    pub alps_age_gap: usize,
+    pub tournament: TournamentKind,
+    /// Opponents per playoff round, or rounds for the Swiss and random
+    /// opponent tournaments
+    pub games_per_bot_per_round: usize,
+    pub playoff_rounds: usize,
+    /// Number of groups the playoff sorts each round's points into
+    pub playoff_buckets: usize,
+    /// Weights of the game metrics, e.g. `--fitness '{"speed": 1}'`
+    pub fitness: FitnessWeights,
+    /// Play every pairing a second time with swapped colours on the same map
+    pub symmetric_games: bool,
+    /// Games every bot plays against hall of fame champions each iteration
+    pub hall_of_fame_games: usize,
+    /// Champions kept, the oldest are dropped first
+    pub hall_of_fame_size: usize,
+    /// Iterations between measuring the champion against fixed reference
+    /// opponents, 0 turns the benchmark off
+    pub benchmark_interval: usize,
+    /// Games per reference opponent, half of them as blue
+    pub benchmark_games: usize,
+    /// How much a single game moves the Elo ratings
+    pub elo_k_factor: f64,
+    pub cull_by: CullKey,
+    /// Share of novelty in the hybrid fitness of `cull_by: novelty`, 1 is
+    /// pure novelty search
+    pub novelty_weight: f64,
+    /// Nearest behaviours a bot's novelty is measured against
+    pub novelty_neighbours: usize,
+    /// Most novel bots of every island archived each iteration
+    pub novelty_archive_additions: usize,
+    /// Behaviours kept in the archive, the oldest are dropped first
+    pub novelty_archive_size: usize,
+    /// Keep the bot with the best benchmark win rate of every behaviour cell
+    /// and copy mutants from those instead of the survivors, see `map_elites`
+    pub map_elites: bool,
+    /// The two behaviours that span the grid, e.g. `'["engagement", "spread"]'`
+    pub map_elites_dimensions: [BehaviourDimension; 2],
+    /// Cells along each dimension
+    pub map_elites_bins: usize,
+    /// Adapt the number of mutations per child with the 1/5 success rule,
+    /// see `adaptation`
+    pub adaptive_mutation: bool,
+    /// Iterations the champion's improvement against the benchmark
+    /// references is measured over
+    pub adaptation_window: usize,
+    pub max_mutation_strength: f64,
+    /// Iterations without a better benchmark win rate of the champion before
+    /// the weakest species are replaced with random bots, 0 never restarts
+    pub stagnation_restart: usize,
+    /// Species of every island replaced in a restart
+    pub restart_species: usize,
+    /// Stop once this many iterations are done, counting from the start of
+    /// the run, not the resume
+    pub max_iterations: Option<usize>,
+    /// Stop after training this long, measured from the start of this
+    /// invocation
+    pub time_budget_seconds: Option<u64>,
+    /// Stop once this many games were played in the run, benchmark games
+    /// included and games answered from the cache not
+    pub max_games: Option<usize>,
+    /// Stop once a benchmark shows the champion winning at least this share
+    /// of its games against `target_reference`
+    pub target_win_rate: Option<f64>,
+    pub target_reference: Reference,
+    /// Stop after this many iterations without a better benchmark win rate
+    /// of the champion, restarts don't reset the count
+    pub stagnation_limit: Option<usize>,
+    /// Picks which bots culling removes, applied from the worst end
+    pub survivor_selection: Selection,
+    /// Picks the survivors that mutants are copied from
+    pub parent_selection: Selection,
+    pub save_file: String,
+    /// Older checkpoints kept next to the save file
+    pub checkpoints_to_keep: usize,
+    pub bind_address: String,
+    /// Master seed of the run, a random one is picked when missing
+    pub seed: Option<u64>,
+    /// Threads used to play games, `None` uses one per core
+    pub worker_threads: Option<usize>,
+    /// Skip pairings that were already played, see `GameCache`
+    pub cache_games: bool,
+    /// One row per iteration next to the save file
+    pub metrics_log: MetricsFormat,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            num_robots: 200,
+            surviving_robots: 50,
+            num_species: 15,
+            crossover_interval: 5,
+            min_bots_per_species: 3,
+            islands: 1,
+            migration_interval: 10,
+            migrants: 2,
+            migration_topology: MigrationTopology::Ring,
+            coevolution: false,
+            coevolution_games: 10,
+            alps: false,
+            alps_age_gap: 10,
+            tournament: TournamentKind::Playoff,
+            games_per_bot_per_round: 2,
+            playoff_rounds: 3,
+            playoff_buckets: 5,
+            fitness: FitnessWeights::default(),
+            symmetric_games: false,
+            hall_of_fame_games: 2,
+            hall_of_fame_size: 100,
+            benchmark_interval: 10,
+            benchmark_games: 10,
+            elo_k_factor: 24.0,
+            cull_by: CullKey::Score,
+            novelty_weight: 0.5,
+            novelty_neighbours: 15,
+            novelty_archive_additions: 2,
+            novelty_archive_size: 500,
+            map_elites: false,
+            map_elites_dimensions: [BehaviourDimension::Engagement, BehaviourDimension::Spread],
+            map_elites_bins: 10,
+            adaptive_mutation: false,
+            adaptation_window: 20,
+            max_mutation_strength: 8.0,
+            stagnation_restart: 0,
+            restart_species: 5,
+            max_iterations: None,
+            time_budget_seconds: None,
+            max_games: None,
+            target_win_rate: None,
+            target_reference: Reference::Template,
+            stagnation_limit: None,
+            survivor_selection: Selection::default(),
+            parent_selection: Selection::default(),
+            save_file: "bots.cbor".to_string(),
+            checkpoints_to_keep: 5,
+            bind_address: "127.0.0.1:8080".to_string(),
+            seed: None,
+            worker_threads: None,
+            cache_games: true,
+            metrics_log: MetricsFormat::Csv,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(PathBuf, std::io::Error),
+    Parse(String),
+    MissingValue(String),
+    UnexpectedArgument(String),
+    Invalid(String),
+    Usage(String),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(path, e) => write!(f, "could not read {}: {e}", path.display()),
+            ConfigError::Parse(e) => write!(f, "invalid configuration: {e}"),
+            ConfigError::MissingValue(flag) => write!(f, "{flag} needs a value"),
+            ConfigError::UnexpectedArgument(arg) => write!(f, "unexpected argument {arg:?}"),
+            ConfigError::Invalid(e) => write!(f, "invalid configuration: {e}"),
+            ConfigError::Usage(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl Config {
+    /// Builds the configuration from command line arguments, not including
+    /// the program name.
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Config, ConfigError> {
+        let mut config_file = None;
+        let mut overrides = serde_json::Map::new();
+        let defaults = serde_json::to_value(Config::default()).unwrap();
+
+        let mut args = args.into_iter();
+        while let Some(arg) = args.next() {
+            let Some(flag) = arg.strip_prefix("--") else {
+                return Err(ConfigError::UnexpectedArgument(arg));
+            };
+            let value = args
+                .next()
+                .ok_or_else(|| ConfigError::MissingValue(arg.clone()))?;
+
+            if flag == "config" {
+                config_file = Some(PathBuf::from(value));
+            } else {
+                let field = flag.replace('-', "_");
+                // string fields, like a file name, take the value as it is, so
+                // `--save-file 2024` stays a name. Anything else that is not
+                // valid JSON, like an enum variant, is taken as a string too
+                let value = match defaults.get(&field) {
+                    Some(Value::String(_)) => Value::String(value),
+                    _ => serde_json::from_str(&value).unwrap_or(Value::String(value)),
+                };
+                overrides.insert(field, value);
+            }
+        }
+
+        let mut config = match config_file {
+            Some(path) => {
+                let file = OpenOptions::new()
+                    .read(true)
+                    .open(&path)
+                    .map_err(|e| ConfigError::Io(path.clone(), e))?;
+                serde_json::from_reader(file).map_err(|e| ConfigError::Parse(e.to_string()))?
+            }
+            None => serde_json::Map::new(),
+        };
+        config.extend(overrides);
+
+        let config: Config = serde_json::from_value(Value::Object(config))
+            .map_err(|e| ConfigError::Parse(e.to_string()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+
+        if self.surviving_robots == 0 || self.surviving_robots >= self.num_robots {
+            return invalid(format!(
+                "surviving_robots ({}) should be between 1 and num_robots ({})",
+                self.surviving_robots, self.num_robots
+            ));
+        }
+        if self.num_species < 2 {
+            return invalid("num_species should be at least 2".to_string());
+        }
+        if self.crossover_interval == 0 {
+            return invalid("crossover_interval should be at least 1".to_string());
+        }
+        if self.min_bots_per_species == 0 {
+            return invalid("min_bots_per_species should be at least 1".to_string());
+        }
+        if self.islands == 0 {
+            return invalid("islands should be at least 1".to_string());
+        }
+        if self.coevolution && self.islands < 2 {
+            return invalid("coevolution needs at least 2 islands".to_string());
+        }
+        if self.coevolution && self.coevolution_games == 0 {
+            return invalid("coevolution_games should be at least 1".to_string());
+        }
+        if self.alps && self.islands < 2 {
+            return invalid("alps needs at least 2 islands as age layers".to_string());
+        }
+        if self.alps && self.coevolution {
+            return invalid("alps and coevolution can't be combined".to_string());
+        }
+        if self.alps_age_gap == 0 {
+            return invalid("alps_age_gap should be at least 1".to_string());
+        }
+        if self.migrants > self.surviving_robots {
+            return invalid(format!(
+                "migrants ({}) should not be more than surviving_robots ({})",
+                self.migrants, self.surviving_robots
+            ));
+        }
+        if self.playoff_buckets == 0 {
+            return invalid("playoff_buckets should be at least 1".to_string());
+        }
+        if self.playoff_rounds == 0 {
+            return invalid("playoff_rounds should be at least 1".to_string());
+        }
+        if self.games_per_bot_per_round == 0 {
+            return invalid("games_per_bot_per_round should be at least 1".to_string());
+        }
+        if self.hall_of_fame_size == 0 {
+            return invalid("hall_of_fame_size should be at least 1".to_string());
+        }
+        if self.benchmark_interval > 0 && self.benchmark_games == 0 {
+            return invalid("benchmark_games should be at least 1 when benchmark_interval is set".to_string());
+        }
+        if self.elo_k_factor.is_nan() || self.elo_k_factor <= 0.0 {
+            return invalid("elo_k_factor should be positive".to_string());
+        }
+        if !(0.0..=1.0).contains(&self.novelty_weight) {
+            return invalid("novelty_weight should be between 0 and 1".to_string());
+        }
+        if self.novelty_neighbours == 0 {
+            return invalid("novelty_neighbours should be at least 1".to_string());
+        }
+        if self.map_elites_bins == 0 {
+            return invalid("map_elites_bins should be at least 1".to_string());
+        }
+        if self.map_elites && self.benchmark_games == 0 {
+            return invalid("benchmark_games should be at least 1 for map_elites".to_string());
+        }
+        if self.adaptation_window == 0 {
+            return invalid("adaptation_window should be at least 1".to_string());
+        }
+        if self.max_mutation_strength.is_nan() || self.max_mutation_strength < 1.0 {
+            return invalid("max_mutation_strength should be at least 1".to_string());
+        }
+        if self.measures_progress() && self.benchmark_games == 0 {
+            return invalid(
+                "benchmark_games should be at least 1 for adaptive_mutation and the stagnation checks"
+                    .to_string(),
+            );
+        }
+        if self.stagnation_restart > 0 && self.restart_species >= self.num_species {
+            return invalid(format!(
+                "restart_species ({}) should be less than num_species ({})",
+                self.restart_species, self.num_species
+            ));
+        }
+        if let Some(target) = self.target_win_rate {
+            if !(target > 0.0 && target <= 1.0) {
+                return invalid("target_win_rate should be above 0 and at most 1".to_string());
+            }
+            if self.benchmark_interval == 0 {
+                return invalid("target_win_rate needs a benchmark_interval".to_string());
+            }
+        }
+        self.survivor_selection
+            .validate()
+            .map_err(|e| ConfigError::Invalid(format!("survivor_selection: {e}")))?;
+        self.parent_selection
+            .validate()
+            .map_err(|e| ConfigError::Invalid(format!("parent_selection: {e}")))?;
+        if self.worker_threads == Some(0) {
+            return invalid("worker_threads should be at least 1".to_string());
+        }
+
+        if self.tournament == TournamentKind::Playoff {
+            self.validate_playoff()?;
+        }
+
+        Ok(())
+    }
+
+    fn validate_playoff(&self) -> Result<(), ConfigError> {
+        let invalid = |message: String| Err(ConfigError::Invalid(message));
+
+        // every playoff round splits the population into thirds, the smallest
+        // segment still needs enough distinct opponents
+        let mut segment_size = self.num_robots;
+        for _round in 1..self.playoff_rounds {
+            segment_size /= 3;
+            if segment_size <= self.games_per_bot_per_round {
+                return invalid(format!(
+                    "with {} robots and {} playoff rounds the last round has segments of {segment_size} bots, \
+                     which is not more than games_per_bot_per_round ({}), use fewer playoff rounds or \
+                     another tournament",
+                    self.num_robots, self.playoff_rounds, self.games_per_bot_per_round
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn worker_threads(&self) -> usize {
+        self.worker_threads.unwrap_or_else(|| {
+            std::thread::available_parallelism()
+                .map(|threads| threads.get())
+                .unwrap_or(1)
+        })
+    }
+
+    /// Whether anything needs the champion's benchmark win rate every
+    /// iteration, see `Adaptation::record`
+    pub fn measures_progress(&self) -> bool {
+        self.adaptive_mutation || self.stagnation_restart > 0 || self.stagnation_limit.is_some()
+    }
+
+    /// Path of a file that belongs to this run, stored next to the save file.
+    pub fn run_file(&self, extension: &str) -> PathBuf {
+        Path::new(&self.save_file).with_extension(extension)
+    }
+
+    /// Writes the configuration as it is actually used next to the save file.
+    pub fn write_effective(&self) {
+        let path = self.run_file("config.json");
+        match OpenOptions::new()
+            .write(true)
+            .truncate(true)
+            .create(true)
+            .open(&path)
+        {
+            Ok(file) => serde_json::to_writer_pretty(file, self).unwrap(),
+            Err(e) => eprintln!("failed to write {}: {e}", path.display()),
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use tokio::sync::{watch, Notify};
+
+/// Lets signals, websocket clients and the pause file steer the training loop.
+/// The loop only looks at it between iterations, so nothing in flight is lost.
+pub struct TrainingControl {
+    stop: AtomicBool,
+    paused: AtomicBool,
+    /// Training is also paused while this file exists
+    pause_file: PathBuf,
+    changed: Notify,
+    shutdown: watch::Sender<bool>,
+}
+
+impl TrainingControl {
+    pub fn new(pause_file: PathBuf) -> Arc<TrainingControl> {
+        Arc::new(TrainingControl {
+            stop: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            pause_file,
+            changed: Notify::new(),
+            shutdown: watch::channel(false).0,
+        })
+    }
+
+    /// Asks the training loop to stop after the current iteration.
+    pub fn request_stop(&self) {
+        self.stop.store(true, Ordering::SeqCst);
+        self.changed.notify_waiters();
+    }
+
+    pub fn stop_requested(&self) -> bool {
+        self.stop.load(Ordering::SeqCst)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::SeqCst);
+        self.changed.notify_waiters();
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst) || self.pause_file.exists()
+    }
+
+    /// Returns once training is no longer paused or a stop was requested.
+    pub async fn wait_while_paused(&self) {
+        if !self.is_paused() {
+            return;
+        }
+
+        println!(
+            "Training paused, resume from the websocket or remove {}",
+            self.pause_file.display()
+        );
+        while self.is_paused() && !self.stop_requested() {
+            // the pause file can't notify, so check it every second
+            tokio::select! {
+                _ = self.changed.notified() => {}
+                _ = tokio::time::sleep(Duration::from_secs(1)) => {}
+            }
+        }
+        if !self.stop_requested() {
+            println!("Training resumed");
+        }
+    }
+
+    /// Tells every websocket connection to close.
+    pub fn shutdown(&self) {
+        self.shutdown.send_replace(true);
+    }
+
+    pub fn subscribe_shutdown(&self) -> watch::Receiver<bool> {
+        self.shutdown.subscribe()
+    }
+}
+
+/// The first SIGINT or SIGTERM lets the current iteration finish and save, a
+/// second one exits immediately.
+pub fn listen_for_signals(control: Arc<TrainingControl>) {
+    tokio::spawn(async move {
+        loop {
+            wait_for_signal().await;
+
+            if control.stop_requested() {
+                eprintln!("Stopping immediately");
+                std::process::exit(130);
+            }
+            eprintln!("Finishing the current iteration, signal again to stop immediately");
+            control.request_stop();
+        }
+    });
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
@@ -0,0 +1,142 @@
+use std::collections::BTreeMap;
+
+use futures::future::join_all;
+use logic::{MainOutput, Team};
+
+use crate::fitness::GAME_LENGTH;
+use crate::expression::Expression;
+use crate::game_cache::{CacheSession, CachedGame, GameKey, GameSummary};
+use crate::Bot;
+
+/// Plays a single game between two genomes. The genomes are borrowed mutably
+/// so their `times_used` counters are updated.
+pub async fn play_genomes(
+    blue: &mut Expression,
+    red: &mut Expression,
+    seed: Option<String>,
+) -> MainOutput {
+    let mut runners = BTreeMap::new();
+    runners.insert(Team::Blue, Ok(blue));
+    runners.insert(Team::Red, Ok(red));
+
+    logic::run(
+        runners,
+        |_| (),
+        GAME_LENGTH,
+        true,
+        None,
+        logic::GameMode::Normal,
+        seed,
+    )
+    .await
+}
+
+/// One side of a game: a member of the population, whose usage counters are
+/// updated, or a genome from outside of it like a hall of fame champion.
+#[derive(Clone, Copy)]
+pub enum Player<'a> {
+    Bot(usize),
+    Genome(&'a Expression),
+}
+
+impl Player<'_> {
+    fn genome<'a>(&'a self, bots: &'a [Bot]) -> &'a Expression {
+        match self {
+            Player::Bot(index) => &bots[*index].logic,
+            Player::Genome(genome) => genome,
+        }
+    }
+
+    fn add_usage(&self, bots: &mut [Bot], usage: &[usize]) {
+        if let Player::Bot(index) = self {
+            bots[*index].logic.add_usage(usage);
+        }
+    }
+}
+
+/// Plays every `(blue, red)` pairing of population members, see `play_matches`.
+pub async fn play_games(
+    bots: &mut [Bot],
+    pairings: &[(usize, usize)],
+    cache: &mut CacheSession<'_>,
+    master_seed: u64,
+) -> Vec<GameSummary> {
+    let matches = pairings
+        .iter()
+        .map(|&(blue, red)| (Player::Bot(blue), Player::Bot(red)))
+        .collect::<Vec<_>>();
+
+    play_matches(bots, &matches, cache, master_seed).await
+}
+
+/// Plays every `(blue, red)` match concurrently on the blocking thread pool
+/// and returns the results in the same order as `matches`.
+///
+/// Each game gets its own canonical copies of both genomes, the usage
+/// counters are merged back into `bots` once all games are finished, so the
+/// outcome does not depend on which game happens to finish first. Cached
+/// games carry the counters of when they were played, so `times_used` does
+/// not depend on the cache either. Expects population members in canonical
+/// form, see `Expression::canonical`.
+pub async fn play_matches(
+    bots: &mut [Bot],
+    matches: &[(Player<'_>, Player<'_>)],
+    cache: &mut CacheSession<'_>,
+    master_seed: u64,
+) -> Vec<GameSummary> {
+    let mut results = Vec::with_capacity(matches.len());
+    let mut pending = Vec::new();
+
+    for (index, (blue_player, red_player)) in matches.iter().enumerate() {
+        let key = GameKey::new(
+            blue_player.genome(bots),
+            red_player.genome(bots),
+            master_seed,
+        );
+
+        if let Some(game) = cache.get(&key) {
+            blue_player.add_usage(bots, &game.blue_usage);
+            red_player.add_usage(bots, &game.red_usage);
+            results.push(Some(game.summary));
+            continue;
+        }
+
+        let mut blue = blue_player.genome(bots).canonical();
+        let mut red = red_player.genome(bots).canonical();
+        let seed = Some(key.seed.clone());
+
+        results.push(None);
+        pending.push((
+            index,
+            key,
+            tokio::task::spawn_blocking(move || {
+                let output =
+                    futures::executor::block_on(play_genomes(&mut blue, &mut red, seed));
+                (GameSummary::from_output(&output), blue, red)
+            }),
+        ));
+    }
+
+    let (indices, handles): (Vec<_>, Vec<_>) = pending
+        .into_iter()
+        .map(|(index, key, handle)| ((index, key), handle))
+        .unzip();
+
+    for ((index, key), finished) in indices.into_iter().zip(join_all(handles).await) {
+        let (summary, blue, red) = finished.expect("game panicked");
+        let (blue_player, red_player) = matches[index];
+
+        let game = CachedGame {
+            summary,
+            blue_usage: blue.usage(),
+            red_usage: red.usage(),
+        };
+        blue_player.add_usage(bots, &game.blue_usage);
+        red_player.add_usage(bots, &game.red_usage);
+
+        cache.insert(key, game);
+        results[index] = Some(summary);
+    }
+
+    results.into_iter().map(|result| result.unwrap()).collect()
+}
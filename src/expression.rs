@@ -1,4 +1,5 @@
 use std::collections::BTreeMap;
+use std::hash::Hasher;
 
 use logic::{ActionType, Coords, Id, ObjDetails, RobotRunner, Team, Unit};
 use rand::seq::SliceRandom;
@@ -59,6 +60,116 @@ impl Expression {
         self.times_used = 0;
         self.kind.clear_times_used();
     }
+
+    /// Usage counters of every node in preorder, see `add_usage`
+    pub fn usage(&self) -> Vec<usize> {
+        let mut usage = Vec::with_capacity(self.size());
+        self.collect_usage(&mut usage);
+        usage
+    }
+
+    fn collect_usage(&self, usage: &mut Vec<usize>) {
+        usage.push(self.times_used);
+        match &self.kind {
+            ExpressionKind::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                condition.collect_usage(usage);
+                then.collect_usage(usage);
+                otherwise.collect_usage(usage);
+            }
+            ExpressionKind::GreaterThan { left, right } | ExpressionKind::Equals { left, right } => {
+                left.collect_usage(usage);
+                right.collect_usage(usage);
+            }
+            _ => (),
+        }
+    }
+
+    /// Adds the usage counters of a copy of this expression that was
+    /// evaluated somewhere else, as returned by its `usage`.
+    pub fn add_usage(&mut self, usage: &[usize]) {
+        self.add_usage_from(&mut usage.iter());
+    }
+
+    fn add_usage_from(&mut self, usage: &mut std::slice::Iter<usize>) {
+        self.times_used += usage.next().copied().unwrap_or(0);
+        match &mut self.kind {
+            ExpressionKind::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                condition.add_usage_from(usage);
+                then.add_usage_from(usage);
+                otherwise.add_usage_from(usage);
+            }
+            ExpressionKind::GreaterThan { left, right } | ExpressionKind::Equals { left, right } => {
+                left.add_usage_from(usage);
+                right.add_usage_from(usage);
+            }
+            _ => (),
+        }
+    }
+
+    /// Number of nodes in the expression tree
+    pub fn size(&self) -> usize {
+        1 + match &self.kind {
+            ExpressionKind::If {
+                condition,
+                then,
+                otherwise,
+            } => condition.size() + then.size() + otherwise.size(),
+            ExpressionKind::GreaterThan { left, right } | ExpressionKind::Equals { left, right } => {
+                left.size() + right.size()
+            }
+            _ => 0,
+        }
+    }
+
+    /// Formats the expression as JavaScript, `Display` gives Python.
+    pub fn to_js(&self) -> JsExpression<'_> {
+        JsExpression(&self.kind)
+    }
+
+    /// Copy of the expression with `times_used` reset and the operands of
+    /// commutative operators in a fixed order, so equivalent genomes compare equal.
+    pub fn canonical(&self) -> Expression {
+        Expression::new(self.kind.canonical().0)
+    }
+
+    /// Hash of the canonical form. Uses a fixed hash function over a fixed
+    /// encoding of the nodes, so the value is stable between runs and Rust
+    /// releases and can be stored on disk.
+    pub fn structural_hash(&self) -> u64 {
+        self.kind.canonical().1
+    }
+}
+
+/// 64 bit FNV-1a. `DefaultHasher` is randomly keyed per process, which makes it
+/// useless for hashes that are persisted. Only fed through `write`, the other
+/// `Hasher` methods use native endianness.
+struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher(0xcbf29ce484222325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
 }
 
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -551,6 +662,107 @@ impl ExpressionKind {
         }
     }
 
+    /// Canonical form and its structural hash. Built bottom up, so every
+    /// node is canonicalised and hashed once: the operands of `Equals` are
+    /// ordered by the hashes their own call already returned.
+    fn canonical(&self) -> (ExpressionKind, u64) {
+        let mut hasher = StableHasher::default();
+        hasher.write(&[self.tag()]);
+        let child = |expression: &Expression| {
+            let (kind, hash) = expression.kind.canonical();
+            (Box::new(Expression::new(kind)), hash)
+        };
+
+        let kind = match self {
+            ExpressionKind::If {
+                condition,
+                then,
+                otherwise,
+            } => {
+                let (condition, condition_hash) = child(condition);
+                let (then, then_hash) = child(then);
+                let (otherwise, otherwise_hash) = child(otherwise);
+                for hash in [condition_hash, then_hash, otherwise_hash] {
+                    hasher.write(&hash.to_le_bytes());
+                }
+                ExpressionKind::If {
+                    condition,
+                    then,
+                    otherwise,
+                }
+            }
+            ExpressionKind::GreaterThan { left, right } => {
+                let (left, left_hash) = child(left);
+                let (right, right_hash) = child(right);
+                hasher.write(&left_hash.to_le_bytes());
+                hasher.write(&right_hash.to_le_bytes());
+                ExpressionKind::GreaterThan { left, right }
+            }
+            ExpressionKind::Equals { left, right } => {
+                let mut left = child(left);
+                let mut right = child(right);
+                if left.1 > right.1 {
+                    std::mem::swap(&mut left, &mut right);
+                }
+                hasher.write(&left.1.to_le_bytes());
+                hasher.write(&right.1.to_le_bytes());
+                ExpressionKind::Equals {
+                    left: left.0,
+                    right: right.0,
+                }
+            }
+            ExpressionKind::ConstantNumber(value) => {
+                hasher.write(&value.to_le_bytes());
+                self.clone()
+            }
+            ExpressionKind::ConstantBoolean(value) => {
+                hasher.write(&[*value as u8]);
+                self.clone()
+            }
+            ExpressionKind::ConstantMove(value) => {
+                let (kind, direction) = match value {
+                    Move::Attack(direction) => (0, direction),
+                    Move::Move(direction) => (1, direction),
+                };
+                let direction = match direction {
+                    Direction::North => 0,
+                    Direction::East => 1,
+                    Direction::South => 2,
+                    Direction::West => 3,
+                };
+                hasher.write(&[kind, direction]);
+                self.clone()
+            }
+            other => other.clone(),
+        };
+        (kind, hasher.finish())
+    }
+
+    /// Fixed number of every node type for `canonical`, independent of the
+    /// declaration order
+    fn tag(&self) -> u8 {
+        match self {
+            ExpressionKind::If { .. } => 0,
+            ExpressionKind::ConstantNumber(_) => 1,
+            ExpressionKind::ConstantBoolean(_) => 2,
+            ExpressionKind::ConstantMove(_) => 3,
+            ExpressionKind::AlliedSurroundingTiles => 4,
+            ExpressionKind::EnemySurroundingTiles => 5,
+            ExpressionKind::AttackNearestEnemy => 6,
+            ExpressionKind::MoveToNearestEnemy => 7,
+            ExpressionKind::DistanceToNearestEnemy => 8,
+            ExpressionKind::DistanceToNearestAlly => 9,
+            ExpressionKind::DistanceToCenter => 10,
+            ExpressionKind::Health => 11,
+            ExpressionKind::X => 12,
+            ExpressionKind::Y => 13,
+            ExpressionKind::GreaterThan { .. } => 14,
+            ExpressionKind::Equals { .. } => 15,
+            ExpressionKind::ClosestEnemyHealth => 16,
+            ExpressionKind::ClosestAllyHealth => 17,
+        }
+    }
+
     fn simplify(self) -> ExpressionKind {
         match self {
             ExpressionKind::If {
@@ -671,6 +883,65 @@ impl core::fmt::Display for ExpressionKind {
     }
 }
 
+pub struct JsExpression<'a>(&'a ExpressionKind);
+
+impl std::fmt::Display for JsExpression<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.0 {
+            ExpressionKind::If {
+                condition,
+                then,
+                otherwise,
+            } => write!(
+                f,
+                "({}) ? ({}) : ({})",
+                condition.to_js(),
+                then.to_js(),
+                otherwise.to_js()
+            ),
+            ExpressionKind::ConstantNumber(num) => write!(f, "{num}"),
+            ExpressionKind::ConstantBoolean(b) => write!(f, "{b}"),
+            ExpressionKind::ConstantMove(mv) => write!(f, "{mv}"),
+            ExpressionKind::Health => write!(f, "unit.health"),
+            ExpressionKind::X => write!(f, "unit.coords.x"),
+            ExpressionKind::Y => write!(f, "unit.coords.y"),
+            ExpressionKind::GreaterThan { left, right } => {
+                write!(f, "({}) > ({})", left.to_js(), right.to_js())
+            }
+            // actions are objects, `===` would compare their identity
+            ExpressionKind::Equals { left, right } => match left.kind.get_type() {
+                ValueType::Move => {
+                    write!(f, "sameAction({}, {})", left.to_js(), right.to_js())
+                }
+                _ => write!(f, "({}) === ({})", left.to_js(), right.to_js()),
+            },
+            ExpressionKind::AlliedSurroundingTiles => {
+                write!(f, "friendlySurroundingTiles(unit.coords, state)")
+            }
+            ExpressionKind::EnemySurroundingTiles => {
+                write!(f, "unsafeSurroundingTiles(unit.coords, state)")
+            }
+            ExpressionKind::AttackNearestEnemy => write!(
+                f,
+                "Action.attack(unit.coords.directionTo(closestEnemy.coords))"
+            ),
+            ExpressionKind::MoveToNearestEnemy => write!(
+                f,
+                "Action.move(unit.coords.directionTo(closestEnemy.coords))"
+            ),
+            ExpressionKind::DistanceToNearestEnemy => {
+                write!(f, "closestEnemy.coords.distanceTo(unit.coords)")
+            }
+            ExpressionKind::DistanceToNearestAlly => {
+                write!(f, "closestAlly.coords.distanceTo(unit.coords)")
+            }
+            ExpressionKind::DistanceToCenter => write!(f, "new Coords(9, 9).distanceTo(unit.coords)"),
+            ExpressionKind::ClosestEnemyHealth => write!(f, "closestEnemy.health"),
+            ExpressionKind::ClosestAllyHealth => write!(f, "closestAlly.health"),
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum Move {
     Attack(Direction),
@@ -750,9 +1021,157 @@ impl RobotRunner for Expression {
     }
 }
 
-// #[async_trait::async_trait]
-// impl RobotRunner for &mut Expression {
-//     async fn run(&mut self, input: logic::ProgramInput<'_>) -> logic::ProgramResult {
-//         (**self).run(input).await
-//     }
-// }
+#[async_trait::async_trait]
+impl RobotRunner for &mut Expression {
+    async fn run(&mut self, input: logic::ProgramInput<'_>) -> logic::ProgramResult {
+        (**self).run(input).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::borrow::Cow;
+    use std::collections::HashMap;
+
+    use logic::{BasicObj, Obj, State, UnitType};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+
+    /// A random genome built the way `generate_bot` builds one
+    fn genome(seed: u64) -> Expression {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let mut expression = Expression::new(ExpressionKind::ConstantMove(Move::Attack(Direction::South)));
+        for _ in 0..10 {
+            expression.mutate(&mut rng, true);
+        }
+        expression
+    }
+
+    fn equals(left: ExpressionKind, right: ExpressionKind) -> Expression {
+        Expression::new(ExpressionKind::Equals {
+            left: Expression::new_box(left),
+            right: Expression::new_box(right),
+        })
+    }
+
+    /// Two blue and two red units spread over the map
+    fn state() -> State {
+        let units = [
+            (Coords(3, 4), Team::Blue, 5),
+            (Coords(4, 4), Team::Blue, 2),
+            (Coords(5, 4), Team::Red, 4),
+            (Coords(12, 9), Team::Red, 5),
+        ];
+        let mut state = State {
+            objs: BTreeMap::new(),
+            grid: HashMap::new(),
+            teams: HashMap::new(),
+            turn: 1,
+        };
+        for (index, (coords, team, health)) in units.into_iter().enumerate() {
+            let id = Id(index);
+            let unit = Unit {
+                type_: UnitType::Soldier,
+                health,
+                team,
+            };
+            state.objs.insert(id, Obj(BasicObj { id, coords }, ObjDetails::Unit(unit)));
+            state.grid.insert(coords, id);
+            state.teams.entry(team).or_insert(vec![]).push(id);
+        }
+        state
+    }
+
+    fn same(a: Result<Value, ()>, b: Result<Value, ()>) -> bool {
+        match (a, b) {
+            (Ok(Value::Number(a)), Ok(Value::Number(b))) => a == b,
+            (Ok(Value::Boolean(a)), Ok(Value::Boolean(b))) => a == b,
+            (Ok(Value::Move(a)), Ok(Value::Move(b))) => a == b,
+            (Err(()), Err(())) => true,
+            _ => false,
+        }
+    }
+
+    #[test]
+    fn equals_operands_hash_in_either_order() {
+        let a = equals(ExpressionKind::X, ExpressionKind::Health);
+        let b = equals(ExpressionKind::Health, ExpressionKind::X);
+
+        assert_eq!(a.structural_hash(), b.structural_hash());
+        assert_eq!(a.canonical(), b.canonical());
+
+        let greater = |left, right| {
+            Expression::new(ExpressionKind::GreaterThan {
+                left: Expression::new_box(left),
+                right: Expression::new_box(right),
+            })
+        };
+        assert_ne!(
+            greater(ExpressionKind::X, ExpressionKind::Health).structural_hash(),
+            greater(ExpressionKind::Health, ExpressionKind::X).structural_hash()
+        );
+    }
+
+    #[test]
+    fn usage_does_not_change_the_hash() {
+        let input = logic::ProgramInput {
+            state: Cow::Owned(state()),
+            team: Team::Blue,
+        };
+        for seed in 0..50 {
+            let mut expression = genome(seed);
+            let hash = expression.structural_hash();
+            for (id, obj) in input.state.objs.clone() {
+                if let ObjDetails::Unit(unit) = &obj.1 {
+                    let _ = expression.eval(&input, id, unit);
+                }
+            }
+
+            assert!(expression.usage().iter().any(|&used| used > 0));
+            assert_eq!(expression.structural_hash(), hash);
+            assert!(expression.canonical().usage().iter().all(|&used| used == 0));
+        }
+    }
+
+    #[test]
+    fn canonical_form_evaluates_like_the_original() {
+        let state = state();
+        // one order of every pair is swapped by `canonical`
+        let attack_west = || ExpressionKind::ConstantMove(Move::Attack(Direction::West));
+        let reordered = [
+            equals(ExpressionKind::Y, ExpressionKind::DistanceToNearestEnemy),
+            equals(ExpressionKind::DistanceToNearestEnemy, ExpressionKind::Y),
+            equals(attack_west(), ExpressionKind::AttackNearestEnemy),
+            equals(ExpressionKind::AttackNearestEnemy, attack_west()),
+        ];
+        for pair in reordered.chunks(2) {
+            assert!(pair.iter().any(|expression| expression.canonical() != *expression));
+        }
+
+        for mut expression in (0..200).map(genome).chain(reordered) {
+            let mut canonical = expression.canonical();
+            assert_eq!(canonical.structural_hash(), expression.structural_hash());
+            assert_eq!(canonical.canonical(), canonical);
+
+            for team in [Team::Blue, Team::Red] {
+                let input = logic::ProgramInput {
+                    state: Cow::Borrowed(&state),
+                    team,
+                };
+                for (id, obj) in state.objs.iter() {
+                    if let ObjDetails::Unit(unit) = &obj.1 {
+                        assert!(
+                            same(
+                                expression.eval(&input, *id, unit),
+                                canonical.eval(&input, *id, unit)
+                            ),
+                            "{expression} and {canonical} differ"
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
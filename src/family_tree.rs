@@ -7,7 +7,7 @@ use crate::{Bot, BotScore};
 const FIRST_NAMES: [&'static str; 4096] = include!("../first-names.json");
 const LAST_NAMES: [&'static str; 4096] = include!("../last-names.json");
 
-#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Species(pub u64);
 
 impl std::fmt::Display for Species {
@@ -55,7 +55,8 @@ impl<'de> Deserialize<'de> for Species {
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
 struct SpeciesInfo {
-    round_introduced: usize,
+    /// `None` if the species already existed when the tree was started
+    round_introduced: Option<usize>,
     round_extinct:Option<usize>,
     parents: Option<[Species;2]>,
     best_score: BotScore
@@ -69,23 +70,47 @@ impl FamilyTree {
         FamilyTree(HashMap::new())
     }
 
+    /// Tree for a population whose history was not recorded, e.g. one loaded
+    /// from an old save file. Every species is marked as predating the tree.
+    pub fn from_population(bots: &[Bot]) -> FamilyTree {
+        let mut tree = FamilyTree::new();
+        for bot in bots {
+            let info = tree.0.entry(bot.species).or_insert(SpeciesInfo {
+                round_introduced: None,
+                round_extinct: None,
+                parents: bot.parents,
+                best_score: bot.score.clone(),
+            });
+
+            if bot.score > info.best_score {
+                info.best_score = bot.score.clone();
+            }
+        }
+        tree
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
     pub fn analize(&mut self, bots: &[Bot], round_number: usize) {
         let mut new_species = HashMap::new();
         for bot in bots {
             let info = new_species.entry(bot.species).or_insert(SpeciesInfo {
-                round_introduced: round_number,
+                round_introduced: Some(round_number),
                 round_extinct: None,
                 parents: bot.parents,
-                best_score: bot.score
+                best_score: bot.score.clone()
             });
 
-            info.best_score = info.best_score.max(bot.score);
+            if bot.score > info.best_score {
+                info.best_score = bot.score.clone();
+            }
         }
 
         for (specie, info) in new_species.iter_mut() {
-            info.round_introduced = match self.0.get(specie) {
-                Some(t) => t.round_introduced,
-                None => round_number
+            if let Some(t) = self.0.get(specie) {
+                info.round_introduced = t.round_introduced;
             }
         }
 
@@ -0,0 +1,234 @@
+use logic::Team;
+use serde::{Deserialize, Serialize};
+
+use crate::game_cache::GameSummary;
+use crate::logic_ext::TeamExt;
+use crate::novelty::Behaviour;
+
+/// Turns after which a game ends in a draw
+pub const GAME_LENGTH: usize = 100;
+
+/// What a single game says about one of its players.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GameMetrics {
+    /// 1 for a win, 0.5 for a draw and 0 for a loss
+    pub result: f64,
+    /// Own units minus enemy units at the end of the game
+    pub unit_differential: isize,
+    /// Own health minus enemy health at the end of the game
+    pub health_differential: isize,
+    /// `None` unless the game was won
+    pub turns_to_victory: Option<usize>,
+    /// Health the enemy lost during the game
+    pub damage_dealt: isize,
+    /// How the game was played, see `novelty`
+    pub behaviour: Behaviour,
+}
+
+impl GameMetrics {
+    pub fn new(summary: &GameSummary, team: Team) -> GameMetrics {
+        let (own_units, own_health, own_damage) = summary.side(team);
+        let (enemy_units, enemy_health, _) = summary.side(team.opposite());
+
+        let result = match summary.winner {
+            Some(winner) if winner == team => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        };
+
+        GameMetrics {
+            result,
+            unit_differential: own_units - enemy_units,
+            health_differential: own_health - enemy_health,
+            turns_to_victory: (summary.winner == Some(team)).then_some(summary.turns),
+            damage_dealt: own_damage,
+            behaviour: summary.behaviour(team),
+        }
+    }
+
+    /// Tournament points, 2 for a win and 1 for a draw
+    pub fn points(&self) -> usize {
+        (self.result * 2.0) as usize
+    }
+}
+
+/// Sums of the metrics of every game a bot played, for reporting.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MetricTotals {
+    pub games: usize,
+    pub wins: usize,
+    pub unit_differential: isize,
+    pub health_differential: isize,
+    pub damage_dealt: isize,
+    pub behaviour: Behaviour,
+}
+
+impl MetricTotals {
+    pub fn add(&mut self, metrics: &GameMetrics) {
+        self.games += 1;
+        if metrics.turns_to_victory.is_some() {
+            self.wins += 1;
+        }
+        self.unit_differential += metrics.unit_differential;
+        self.health_differential += metrics.health_differential;
+        self.damage_dealt += metrics.damage_dealt;
+        self.behaviour.add(&metrics.behaviour);
+    }
+}
+
+/// How much each metric contributes to a game's fitness.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FitnessWeights {
+    pub result: f64,
+    pub unit_differential: f64,
+    pub health_differential: f64,
+    /// Per won game, scaled from 1 for winning immediately to 0 for winning
+    /// on the last turn
+    pub speed: f64,
+    pub damage_dealt: f64,
+}
+
+impl Default for FitnessWeights {
+    fn default() -> Self {
+        // a win is worth 2 like in the tournament points, the differentials
+        // mostly break ties
+        FitnessWeights {
+            result: 2.0,
+            unit_differential: 0.01,
+            health_differential: 0.001,
+            speed: 0.0,
+            damage_dealt: 0.0,
+        }
+    }
+}
+
+impl FitnessWeights {
+    pub fn fitness(&self, metrics: &GameMetrics) -> f64 {
+        let speed = metrics
+            .turns_to_victory
+            .map_or(0.0, |turns| 1.0 - turns.min(GAME_LENGTH) as f64 / GAME_LENGTH as f64);
+
+        self.result * metrics.result
+            + self.unit_differential * metrics.unit_differential as f64
+            + self.health_differential * metrics.health_differential as f64
+            + self.speed * speed
+            + self.damage_dealt * metrics.damage_dealt as f64
+    }
+}
+
+/// Groups `points` out of at most `max_points` into `buckets` equally wide
+/// buckets, so small differences don't decide a playoff round.
+pub fn bucket(points: usize, max_points: usize, buckets: usize) -> usize {
+    points.min(max_points) * buckets / (max_points + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn summary(winner: Option<Team>) -> GameSummary {
+        GameSummary {
+            winner,
+            blue_units: 5,
+            red_units: 2,
+            blue_health: 20,
+            red_health: 7,
+            turns: 60,
+            blue_damage: 30,
+            red_damage: 12,
+            blue_behaviour: Behaviour {
+                engagement: 0.5,
+                ..Default::default()
+            },
+            red_behaviour: Behaviour::default(),
+        }
+    }
+
+    #[test]
+    fn blue_metrics() {
+        let metrics = GameMetrics::new(&summary(Some(Team::Blue)), Team::Blue);
+
+        assert_eq!(metrics.result, 1.0);
+        assert_eq!(metrics.unit_differential, 3);
+        assert_eq!(metrics.health_differential, 13);
+        assert_eq!(metrics.turns_to_victory, Some(60));
+        assert_eq!(metrics.damage_dealt, 30);
+        assert_eq!(metrics.points(), 2);
+    }
+
+    #[test]
+    fn red_metrics() {
+        let metrics = GameMetrics::new(&summary(Some(Team::Blue)), Team::Red);
+
+        assert_eq!(metrics.result, 0.0);
+        assert_eq!(metrics.unit_differential, -3);
+        assert_eq!(metrics.health_differential, -13);
+        assert_eq!(metrics.turns_to_victory, None);
+        assert_eq!(metrics.damage_dealt, 12);
+        assert_eq!(metrics.points(), 0);
+    }
+
+    #[test]
+    fn red_win_mirrors_blue_win() {
+        let blue_win = summary(Some(Team::Blue));
+        let red_win = GameSummary {
+            winner: Some(Team::Red),
+            blue_units: blue_win.red_units,
+            red_units: blue_win.blue_units,
+            blue_health: blue_win.red_health,
+            red_health: blue_win.blue_health,
+            turns: blue_win.turns,
+            blue_damage: blue_win.red_damage,
+            red_damage: blue_win.blue_damage,
+            blue_behaviour: blue_win.red_behaviour,
+            red_behaviour: blue_win.blue_behaviour,
+        };
+
+        assert_eq!(
+            GameMetrics::new(&blue_win, Team::Blue),
+            GameMetrics::new(&red_win, Team::Red)
+        );
+        assert_eq!(
+            GameMetrics::new(&blue_win, Team::Red),
+            GameMetrics::new(&red_win, Team::Blue)
+        );
+    }
+
+    #[test]
+    fn draw_is_half_a_win_for_both_colours() {
+        for team in [Team::Blue, Team::Red] {
+            let metrics = GameMetrics::new(&summary(None), team);
+            assert_eq!(metrics.result, 0.5);
+            assert_eq!(metrics.turns_to_victory, None);
+            assert_eq!(metrics.points(), 1);
+        }
+    }
+
+    #[test]
+    fn weights_combine_metrics() {
+        let weights = FitnessWeights {
+            result: 2.0,
+            unit_differential: 1.0,
+            health_differential: 0.5,
+            speed: 10.0,
+            damage_dealt: 0.1,
+        };
+
+        let blue = GameMetrics::new(&summary(Some(Team::Blue)), Team::Blue);
+        // 2 + 3 + 6.5 + 10 * 0.4 + 3
+        assert!((weights.fitness(&blue) - 18.5).abs() < 1e-9);
+
+        let red = GameMetrics::new(&summary(Some(Team::Blue)), Team::Red);
+        // 0 - 3 - 6.5 + 0 + 1.2
+        assert!((weights.fitness(&red) + 8.3).abs() < 1e-9);
+    }
+
+    #[test]
+    fn buckets_cover_the_range() {
+        let buckets = (0..=8).map(|points| bucket(points, 8, 5)).collect::<Vec<_>>();
+        assert_eq!(buckets, vec![0, 0, 1, 1, 2, 2, 3, 3, 4]);
+        assert_eq!(bucket(20, 8, 5), 4);
+    }
+}
@@ -0,0 +1,284 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::OpenOptions;
+use std::path::Path;
+
+use logic::{Id, MainOutput, Obj, ObjDetails, Team, Unit};
+use serde::{Deserialize, Serialize};
+
+use crate::checkpoint::write_atomically;
+use crate::expression::Expression;
+use crate::novelty::Behaviour;
+use crate::Bot;
+
+/// The parts of a `MainOutput` that scoring needs, small enough to keep
+/// around for every pairing.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct GameSummary {
+    pub winner: Option<Team>,
+    pub blue_units: isize,
+    pub red_units: isize,
+    pub blue_health: isize,
+    pub red_health: isize,
+    pub turns: usize,
+    /// Damage dealt by blue, i.e. the health red lost during the game
+    pub blue_damage: isize,
+    /// Damage dealt by red
+    pub red_damage: isize,
+    pub blue_behaviour: Behaviour,
+    pub red_behaviour: Behaviour,
+}
+
+/// Health of every unit of a turn, by team
+fn unit_health(objs: &BTreeMap<Id, Obj>) -> impl Iterator<Item = (Id, Team, isize)> + '_ {
+    objs.iter().filter_map(|(&id, obj)| match obj.1 {
+        ObjDetails::Unit(Unit { team, health, .. }) => Some((id, team, health as isize)),
+        _ => None,
+    })
+}
+
+impl GameSummary {
+    /// Units, health and damage dealt of one side
+    pub fn side(&self, team: Team) -> (isize, isize, isize) {
+        match team {
+            Team::Blue => (self.blue_units, self.blue_health, self.blue_damage),
+            Team::Red => (self.red_units, self.red_health, self.red_damage),
+        }
+    }
+
+    pub fn behaviour(&self, team: Team) -> Behaviour {
+        match team {
+            Team::Blue => self.blue_behaviour,
+            Team::Red => self.red_behaviour,
+        }
+    }
+
+    pub fn from_output(result: &MainOutput) -> GameSummary {
+        let (red_health, blue_health, red_units, blue_units) = result.turns
+            [result.turns.len() - 1]
+            .state
+            .objs
+            .values()
+            .fold(
+                (0, 0, 0, 0),
+                |(red_health, blue_health, red_units, blue_units), b| match b.1 {
+                    ObjDetails::Unit(Unit { team, health, .. }) => match team {
+                        Team::Red => (
+                            red_health + health as isize,
+                            blue_health,
+                            red_units + 1,
+                            blue_units,
+                        ),
+                        Team::Blue => (
+                            red_health,
+                            blue_health + health as isize,
+                            red_units,
+                            blue_units + 1,
+                        ),
+                    },
+                    _ => (red_health, blue_health, red_units, blue_units),
+                },
+            );
+        match result.winner {
+            Some(Team::Red) => assert!(red_units > blue_units),
+            Some(Team::Blue) => assert!(blue_units > red_units),
+            None => assert_eq!(red_units, blue_units),
+        }
+
+        // every bit of health a unit loses between two turns, including what
+        // it had left when it died, counts as damage dealt by the other team
+        let (mut blue_damage, mut red_damage) = (0, 0);
+        for turns in result.turns.windows(2) {
+            let next = &turns[1].state.objs;
+            for (id, team, health) in unit_health(&turns[0].state.objs) {
+                let remaining = match next.get(&id) {
+                    Some(Obj(_, ObjDetails::Unit(unit))) => unit.health as isize,
+                    _ => 0,
+                };
+                let lost = (health - remaining).max(0);
+                match team {
+                    Team::Blue => red_damage += lost,
+                    Team::Red => blue_damage += lost,
+                }
+            }
+        }
+
+        GameSummary {
+            winner: result.winner,
+            blue_units,
+            red_units,
+            blue_health,
+            red_health,
+            turns: result.turns.len(),
+            blue_damage,
+            red_damage,
+            blue_behaviour: Behaviour::from_output(result, Team::Blue),
+            red_behaviour: Behaviour::from_output(result, Team::Red),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct GameKey {
+    pub blue: u64,
+    pub red: u64,
+    pub seed: String,
+}
+
+impl GameKey {
+    /// The seed of a game only depends on the run's master seed and the two
+    /// genomes, so a pairing always plays out the same way within a run. It
+    /// does not depend on who plays which colour, so a rematch with swapped
+    /// sides is played on the same map.
+    pub fn new(blue: &Expression, red: &Expression, master_seed: u64) -> GameKey {
+        let blue = blue.structural_hash();
+        let red = red.structural_hash();
+        let (first, second) = (blue.min(red), blue.max(red));
+
+        GameKey {
+            blue,
+            red,
+            seed: format!("{master_seed:016x}{first:016x}{second:016x}"),
+        }
+    }
+}
+
+/// A played game with the usage counters of both genomes, so a cache hit
+/// leaves a bot with the same `times_used` as replaying the game would.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CachedGame {
+    pub summary: GameSummary,
+    /// `Expression::usage` of the canonical blue genome
+    pub blue_usage: Vec<usize>,
+    pub red_usage: Vec<usize>,
+}
+
+/// Results of already played games, valid because every game is seeded from
+/// its `GameKey`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct GameCache {
+    games: HashMap<GameKey, CachedGame>,
+    /// When disabled every lookup misses and nothing is stored, so hits and
+    /// misses still add up to the games played
+    #[serde(skip)]
+    pub enabled: bool,
+    #[serde(skip)]
+    pub hits: usize,
+    #[serde(skip)]
+    pub misses: usize,
+}
+
+impl GameCache {
+    pub fn load(filename: &Path, enabled: bool) -> GameCache {
+        let mut cache = if enabled && filename.exists() {
+            match OpenOptions::new().read(true).open(filename) {
+                Ok(file) => ciborium::from_reader(file).unwrap_or_else(|e| {
+                    eprintln!("failed to read game cache, starting empty: {e:?}");
+                    GameCache::default()
+                }),
+                Err(_) => GameCache::default(),
+            }
+        } else {
+            GameCache::default()
+        };
+
+        cache.enabled = enabled;
+        cache
+    }
+
+    /// Written atomically, so a crash never leaves a half written cache.
+    pub fn save(&self, filename: &Path) {
+        if !self.enabled {
+            return;
+        }
+
+        if let Err(e) = write_atomically(filename, self, 0) {
+            eprintln!("failed to save game cache: {e}");
+        }
+    }
+
+    /// Read access for playing games, see `CacheSession`
+    pub fn session(&self) -> CacheSession<'_> {
+        CacheSession {
+            cache: self,
+            updates: CacheUpdates::default(),
+        }
+    }
+
+    /// Adds the games and counters of a finished `CacheSession`
+    pub fn merge(&mut self, updates: CacheUpdates) {
+        self.hits += updates.hits;
+        self.misses += updates.misses;
+        self.games.extend(updates.games);
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    /// Drops every game that involves a genome that is neither in the
+    /// population nor in `keep`, so the cache does not grow without bound.
+    pub fn retain_population(&mut self, bots: &[Bot], keep: impl IntoIterator<Item = u64>) {
+        let alive = bots
+            .iter()
+            .map(|bot| bot.logic.structural_hash())
+            .chain(keep)
+            .collect::<HashSet<_>>();
+
+        self.games
+            .retain(|key, _| alive.contains(&key.blue) && alive.contains(&key.red));
+    }
+
+    pub fn reset_statistics(&mut self) {
+        self.hits = 0;
+        self.misses = 0;
+    }
+}
+
+/// Games a `CacheSession` played, to be added with `GameCache::merge`
+#[derive(Debug, Default)]
+pub struct CacheUpdates {
+    games: HashMap<GameKey, CachedGame>,
+    pub hits: usize,
+    /// Games that were actually played
+    pub misses: usize,
+}
+
+/// Looks games up in the cache without changing it, so every island can
+/// play its games at the same time. New games are kept in the session until
+/// it is merged back in island order.
+pub struct CacheSession<'a> {
+    cache: &'a GameCache,
+    updates: CacheUpdates,
+}
+
+impl CacheSession<'_> {
+    pub fn get(&mut self, key: &GameKey) -> Option<CachedGame> {
+        if !self.cache.enabled {
+            self.updates.misses += 1;
+            return None;
+        }
+
+        let result = self
+            .cache
+            .games
+            .get(key)
+            .or_else(|| self.updates.games.get(key))
+            .cloned();
+        if result.is_some() {
+            self.updates.hits += 1;
+        } else {
+            self.updates.misses += 1;
+        }
+        result
+    }
+
+    pub fn insert(&mut self, key: GameKey, game: CachedGame) {
+        if self.cache.enabled {
+            self.updates.games.insert(key, game);
+        }
+    }
+
+    pub fn finish(self) -> CacheUpdates {
+        self.updates
+    }
+}
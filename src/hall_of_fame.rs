@@ -0,0 +1,124 @@
+use logic::Team;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::evaluation::{play_matches, Player};
+use crate::expression::Expression;
+use crate::family_tree::Species;
+use crate::fitness::GameMetrics;
+use crate::rating::{self, Rating};
+use crate::tournament::Evaluation;
+use crate::{Bot, BotScore};
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Champion {
+    pub logic: Expression,
+    pub species: Species,
+    /// Iteration in which the genome first was the best bot
+    pub iteration: usize,
+    pub score: BotScore,
+    pub hash: u64,
+    /// Rating when the champion was added, kept fixed so bots are rated
+    /// against the same opponent every time
+    pub rating: Rating,
+}
+
+/// The best bot of every iteration, without duplicate genomes. Current bots
+/// play against a sample of it so they can't forget how to beat old strategies.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct HallOfFame {
+    champions: Vec<Champion>,
+}
+
+impl HallOfFame {
+    /// Adds the bot unless its genome is already in the hall of fame. Once
+    /// there are more than `max_size` champions the oldest ones are dropped.
+    pub fn add(&mut self, bot: &Bot, iteration: usize, max_size: usize) -> bool {
+        let hash = bot.logic.structural_hash();
+        if self.champions.iter().any(|champion| champion.hash == hash) {
+            return false;
+        }
+
+        self.champions.push(Champion {
+            logic: bot.logic.canonical(),
+            species: bot.species,
+            iteration,
+            score: bot.score.clone(),
+            hash,
+            rating: bot.rating,
+        });
+        if self.champions.len() > max_size {
+            let excess = self.champions.len() - max_size;
+            self.champions.drain(0..excess);
+        }
+        true
+    }
+
+    /// `count` champions picked at random, with replacement so there are
+    /// always exactly `count` unless the hall of fame is empty.
+    pub fn sample<RNG: rand::Rng>(&self, count: usize, rng: &mut RNG) -> Vec<&Champion> {
+        if self.champions.is_empty() {
+            return vec![];
+        }
+
+        (0..count)
+            .map(|_| self.champions.choose(rng).unwrap())
+            .collect()
+    }
+
+    pub fn champions(&self) -> &[Champion] {
+        &self.champions
+    }
+
+    pub fn len(&self) -> usize {
+        self.champions.len()
+    }
+
+    pub fn hashes(&self) -> impl Iterator<Item = u64> + '_ {
+        self.champions.iter().map(|champion| champion.hash)
+    }
+}
+
+/// Every bot plays `hall_of_fame_games` games as blue against randomly picked
+/// champions, and with `symmetric_games` the same games again as red. The
+/// points (2 for a win, 1 for a draw) go to `score.hall_of_fame_wins`, the
+/// metrics to the fitness. Ratings of the bots and their species are updated
+/// against the champions' fixed ratings.
+pub async fn play_hall_of_fame(
+    bots: &mut [Bot],
+    hall_of_fame: &HallOfFame,
+    evaluation: &mut Evaluation<'_>,
+) {
+    let config = evaluation.config;
+    let mut matches = vec![];
+    let mut bot_teams = vec![];
+    for index in 0..bots.len() {
+        for champion in hall_of_fame.sample(config.hall_of_fame_games, &mut evaluation.rng) {
+            matches.push((Player::Bot(index), Player::Genome(&champion.logic)));
+            bot_teams.push((index, Team::Blue, champion.rating));
+            if config.symmetric_games {
+                matches.push((Player::Genome(&champion.logic), Player::Bot(index)));
+                bot_teams.push((index, Team::Red, champion.rating));
+            }
+        }
+    }
+
+    let results = play_matches(bots, &matches, &mut evaluation.cache, evaluation.seed).await;
+
+    for ((index, team, champion_rating), result) in bot_teams.into_iter().zip(results) {
+        let metrics = GameMetrics::new(&result, team);
+        let bot = &mut bots[index];
+        rating::update_against(
+            &mut bot.rating,
+            champion_rating,
+            metrics.result,
+            config.elo_k_factor,
+        );
+        evaluation
+            .species_games
+            .record_against(bot.species, champion_rating, metrics.result);
+        bot.score.hall_of_fame_wins += metrics.points();
+        bot.score.add_game(&metrics, &config.fitness);
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::{HashMap, HashSet};
+
+use rand::Rng;
+use serde::Serialize;
+
+use crate::config::{CullKey, MigrationTopology};
+use crate::family_tree::Species;
+use crate::Bot;
+
+/// Groups the population by `Bot::island`. Bots of islands that no longer
+/// exist, e.g. after lowering `islands` on resume, are spread over the
+/// remaining ones.
+pub fn split(bots: Vec<Bot>, islands: usize) -> Vec<Vec<Bot>> {
+    let mut populations = vec![vec![]; islands];
+    for mut bot in bots {
+        bot.island %= islands;
+        populations[bot.island].push(bot);
+    }
+    populations
+}
+
+/// Sends copies of the best `migrants` bots of every island to its neighbours
+/// as given by `topology`, replacing the last bots there. Expects every island
+/// sorted best first.
+pub fn migrate<RNG: Rng>(
+    islands: &mut [Vec<Bot>],
+    topology: MigrationTopology,
+    migrants: usize,
+    rng: &mut RNG,
+) -> usize {
+    let count = islands.len();
+    if count < 2 {
+        return 0;
+    }
+
+    let mut arrivals = vec![vec![]; count];
+    for (source, bots) in islands.iter().enumerate() {
+        let destinations = match topology {
+            MigrationTopology::Ring => vec![(source + 1) % count],
+            MigrationTopology::FullyConnected => {
+                (0..count).filter(|&other| other != source).collect()
+            }
+            MigrationTopology::Random => {
+                // any island but the source
+                let other = rng.gen_range(0..count - 1);
+                vec![if other >= source { other + 1 } else { other }]
+            }
+        };
+
+        for destination in destinations {
+            for bot in bots.iter().take(migrants) {
+                let mut migrant = bot.clone();
+                migrant.island = destination;
+                arrivals[destination].push(migrant);
+            }
+        }
+    }
+
+    let mut moved = 0;
+    for (island, arriving) in islands.iter_mut().zip(arrivals) {
+        // never push out more than half of an island
+        let arriving = arriving.into_iter().take(island.len() / 2).collect::<Vec<_>>();
+        moved += arriving.len();
+        island.truncate(island.len() - arriving.len());
+        island.extend(arriving);
+    }
+    moved
+}
+
+/// One character per bot, the same character for the same species
+pub fn species_strip(bots: &[Bot], length: usize) -> String {
+    let mut species = HashMap::new();
+    for bot in bots.iter() {
+        let length = species.len();
+
+        species.entry(bot.species).or_insert(
+            *[
+                '-', '#', '*', '&', '$', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9',
+            ]
+            .get(length)
+            .unwrap_or(&'_'),
+        );
+    }
+
+    bots.iter()
+        .take(length)
+        .map(|bot| species.get(&bot.species).copied().unwrap_or('_'))
+        .collect()
+}
+
+/// State of an island after evaluation, for the console and the websocket.
+#[derive(Clone, Serialize, Debug)]
+pub struct IslandSummary {
+    pub island: usize,
+    pub bots: usize,
+    pub species: usize,
+    pub best_species: Species,
+    pub best_fitness: f64,
+    pub best_rating: f64,
+}
+
+impl IslandSummary {
+    /// Expects the island sorted best first
+    pub fn new(island: usize, bots: &[Bot]) -> IslandSummary {
+        IslandSummary {
+            island,
+            bots: bots.len(),
+            species: bots.iter().map(|bot| bot.species).collect::<HashSet<_>>().len(),
+            best_species: bots[0].species,
+            best_fitness: bots[0].score.fitness,
+            best_rating: bots[0].rating.0,
+        }
+    }
+}
+
+impl std::fmt::Display for IslandSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} bots, {} species, best {} (fitness {:.2}, rating {:.0})",
+            self.bots, self.species, self.best_species, self.best_fitness, self.best_rating
+        )
+    }
+}
+
+/// Index of the island whose best bot ranks highest
+pub fn champion_island(islands: &[Vec<Bot>], cull_by: CullKey) -> usize {
+    (0..islands.len())
+        .max_by(|&a, &b| islands[a][0].rank(cull_by).cmp(&islands[b][0].rank(cull_by)))
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::expression::{Expression, ExpressionKind};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn bot(species: u64, island: usize) -> Bot {
+        Bot {
+            species: Species(species),
+            island,
+            ..Bot::from_logic(Expression::new(ExpressionKind::X))
+        }
+    }
+
+    /// `count` islands of `size` bots, every bot's species is its island
+    fn islands(count: usize, size: usize) -> Vec<Vec<Bot>> {
+        (0..count)
+            .map(|island| (0..size).map(|_| bot(island as u64, island)).collect())
+            .collect()
+    }
+
+    #[test]
+    fn split_wraps_removed_islands() {
+        let bots = (0..6).map(|island| bot(island as u64, island)).collect();
+        let populations = split(bots, 4);
+
+        let sizes = populations.iter().map(Vec::len).collect::<Vec<_>>();
+        assert_eq!(sizes, [2, 2, 1, 1]);
+        for (index, population) in populations.iter().enumerate() {
+            assert!(population.iter().all(|bot| bot.island == index));
+        }
+    }
+
+    #[test]
+    fn ring_sends_the_best_to_the_next_island() {
+        let mut islands = islands(3, 6);
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        assert_eq!(migrate(&mut islands, MigrationTopology::Ring, 2, &mut rng), 6);
+        for (index, island) in islands.iter().enumerate() {
+            let source = (index + 2) % 3;
+            assert_eq!(island.len(), 6);
+            assert!(island[..4].iter().all(|bot| bot.species == Species(index as u64)));
+            assert!(island[4..].iter().all(|bot| bot.species == Species(source as u64)));
+            assert!(island.iter().all(|bot| bot.island == index));
+        }
+    }
+
+    #[test]
+    fn migrants_never_replace_more_than_half_of_an_island() {
+        let mut islands = islands(4, 4);
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        // every island would receive 3 * 2 migrants
+        assert_eq!(migrate(&mut islands, MigrationTopology::FullyConnected, 2, &mut rng), 8);
+        for (index, island) in islands.iter().enumerate() {
+            assert_eq!(island.len(), 4);
+            assert!(island[..2].iter().all(|bot| bot.species == Species(index as u64)));
+            assert!(island[2..].iter().all(|bot| bot.species != Species(index as u64)));
+        }
+    }
+
+    #[test]
+    fn random_never_picks_the_source() {
+        for seed in 0..50 {
+            let mut islands = islands(3, 10);
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+
+            let moved = migrate(&mut islands, MigrationTopology::Random, 1, &mut rng);
+            assert_eq!(moved, 3);
+            // a migrant sent back to its source would still count as its own
+            let own = islands
+                .iter()
+                .enumerate()
+                .map(|(index, island)| {
+                    island.iter().filter(|bot| bot.species == Species(index as u64)).count()
+                })
+                .sum::<usize>();
+            assert_eq!(own, 30 - moved);
+        }
+    }
+
+    #[test]
+    fn a_single_island_has_nowhere_to_migrate() {
+        let mut islands = islands(1, 4);
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+
+        assert_eq!(migrate(&mut islands, MigrationTopology::Random, 2, &mut rng), 0);
+        assert_eq!(islands[0].len(), 4);
+    }
+
+    #[test]
+    fn species_strip_marks_species_consistently() {
+        let bots = [3, 3, 7, 3, 9, 7].map(|species| bot(species, 0));
+        assert_eq!(species_strip(&bots, 5), "--#-*");
+
+        let many = (0..20).map(|species| bot(species, 0)).collect::<Vec<_>>();
+        let strip = species_strip(&many, 20);
+        assert_eq!(strip.chars().count(), 20);
+        assert!(strip.ends_with("_____"));
+    }
+}
@@ -1,16 +1,35 @@
+use checkpoint::{Checkpoint, FORMAT_VERSION};
+use cli::Command;
+use config::{Config, CullKey};
+use control::TrainingControl;
+use futures::future::join_all;
 use family_tree::{FamilyTree, Species};
-use futures::future::{BoxFuture, FutureExt};
-use logic::{MainOutput, ObjDetails, RobotRunner, Team, Unit};
+use fitness::{FitnessWeights, GameMetrics, MetricTotals, GAME_LENGTH};
+use game_cache::{GameCache, GameKey};
+use hall_of_fame::{play_hall_of_fame, HallOfFame};
+use adaptation::Adaptation;
+use map_elites::EliteArchive;
+use metrics::{IterationMetrics, PopulationStats, Timings};
+use novelty::{NoveltyArchive, NoveltyRank};
+use ordered_float::OrderedFloat;
+use pareto::ParetoRank;
+use rating::{Rating, SpeciesRatings};
+use selection::Selection;
+use termination::{Progress, StopReason};
+use tournament::{Evaluation, SideBias, Tournament};
+use logic::{MainOutput, RobotRunner, Team, Unit};
 use logic_ext::Direction;
 use rand::prelude::SliceRandom;
 use rand::Rng;
 use sockets::{start_socket, TrainingProgressAnnouncement};
-use std::collections::{BTreeMap, HashMap};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
 
 use std::io::Write;
 
 use std::fs::OpenOptions;
-use std::path::Path;
 use std::time::Instant;
 
 use expression::Expression;
@@ -18,21 +37,35 @@ use serde::{Deserialize, Serialize};
 
 use crate::expression::Move;
 
+mod adaptation;
+mod alps;
+mod benchmark;
 mod expression;
 mod logic_ext;
 mod sockets;
+mod checkpoint;
+mod cli;
+mod coevolution;
+mod commands;
+mod config;
+mod control;
+mod evaluation;
 mod family_tree;
+mod fitness;
+mod game_cache;
+mod hall_of_fame;
+mod island;
+mod map_elites;
+mod metrics;
+mod novelty;
+mod ordered_float;
+mod pareto;
+mod rating;
+mod selection;
+mod termination;
+mod tournament;
 
 
-const SAVE_FILENAME: &'static str = "bots.cbor";
-
-const NUM_ROBOTS: usize = 200;
-const SURVIVING_ROBOTS: usize = 50;
-const NUM_SPECIES: usize = 15;
-const CROSSOVER_INTERVAL: usize = 5;
-const MIN_BOTS_PER_SPECIES: usize = 3;
-const NUMER_OF_GAMES_PER_BOT_PER_ROUND: usize = 2;
-const NUMER_OF_PLAYOFF_ROUNDS: usize = 3;
 
 fn generate_bot<Rng: rand::Rng>(rng: &mut Rng) -> Bot {
     let mut expression = expression::Expression::new(expression::ExpressionKind::ConstantMove(Move::Attack(Direction::South)));
@@ -46,17 +79,81 @@ fn generate_bot<Rng: rand::Rng>(rng: &mut Rng) -> Bot {
         score: Default::default(),
         generation: 0,
         parents: None,
+        rating: Default::default(),
+        island: 0,
+        age: 0,
     };
 }
 
-#[derive(Debug, Clone, Serialize, Default, Ord, PartialEq, PartialOrd, Eq, Copy, Deserialize)]
+fn initial_population<RNG: rand::Rng>(config: &Config, rng: &mut RNG) -> Vec<Bot> {
+    let mut bots = (0..config.num_robots - 1)
+        .map(|_| generate_bot(rng))
+        .collect::<Vec<_>>();
+
+    bots.push(Bot {
+        species: Species(0),
+        logic: benchmark::east_west(),
+        score: Default::default(),
+        generation: 0,
+        parents: None,
+        rating: Default::default(),
+        island: 0,
+        age: 0,
+    });
+    bots
+}
+
+#[derive(Debug, Clone, Serialize, Default, Deserialize)]
+#[serde(default)]
 struct BotScore {
-    wins: [usize; NUMER_OF_PLAYOFF_ROUNDS],
-    friendly_units: isize,
-    enemy_units: isize,
-    friendly_health: isize,
-    enemy_health: isize,
+    /// Points per playoff round, see `tournament`
+    wins: Vec<usize>,
+    /// Weighted sum over every game, see `fitness::FitnessWeights`
+    fitness: f64,
+    metrics: MetricTotals,
     total_wins: usize,
+    /// Points against hall of fame champions, already part of `wins[0]`
+    hall_of_fame_wins: usize,
+    /// Non-dominated front of the metrics, 0 is the Pareto front, see
+    /// `pareto`. Only computed with `cull_by: pareto`
+    pareto_front: usize,
+    crowding_distance: f64,
+    /// Distance of the bot's behaviour to its nearest neighbours, see `novelty`
+    novelty: f64,
+    /// Novelty and score rank mixed by `novelty_weight`
+    hybrid_fitness: f64,
+}
+
+impl BotScore {
+    /// Ranked by points, then by fitness
+    fn key(&self) -> (&[usize], OrderedFloat, usize, usize) {
+        (
+            &self.wins,
+            OrderedFloat(self.fitness),
+            self.total_wins,
+            self.hall_of_fame_wins,
+        )
+    }
+}
+
+impl PartialEq for BotScore {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for BotScore {}
+
+impl PartialOrd for BotScore {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for BotScore {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -66,6 +163,84 @@ struct Bot {
     score: BotScore,
     generation: usize,
     parents: Option<[Species; 2]>,
+    /// Kept across iterations, mutants start with their parent's rating
+    #[serde(default)]
+    rating: Rating,
+    /// Population the bot evolves in, see `island`
+    #[serde(default)]
+    island: usize,
+    /// Iterations since the oldest part of the genome was generated at
+    /// random, mutants inherit it from their parent, see `alps`
+    #[serde(default)]
+    age: usize,
+}
+
+impl Bot {
+    /// A bot that is not part of any population, e.g. loaded from a file
+    fn from_logic(logic: Expression) -> Bot {
+        Bot {
+            logic,
+            species: Species(0),
+            score: Default::default(),
+            generation: 0,
+            parents: None,
+            rating: Default::default(),
+            island: 0,
+            age: 0,
+        }
+    }
+}
+
+/// What bots are ranked by when culling. Only values of the same variant
+/// are ever compared.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Rank {
+    Score(BotScore),
+    Rating(OrderedFloat),
+    Pareto(ParetoRank),
+    Novelty(NoveltyRank),
+}
+
+impl Bot {
+    fn rank(&self, cull_by: CullKey) -> Rank {
+        match cull_by {
+            CullKey::Score => Rank::Score(self.score.clone()),
+            CullKey::Rating => Rank::Rating(OrderedFloat(self.rating.0)),
+            CullKey::Pareto => Rank::Pareto(ParetoRank::of(self)),
+            CullKey::Novelty => Rank::Novelty(NoveltyRank::of(self)),
+        }
+    }
+
+    /// What roulette selection is proportional to
+    fn selection_fitness(&self, cull_by: CullKey) -> f64 {
+        match cull_by {
+            CullKey::Score => self.score.fitness,
+            CullKey::Rating => self.rating.0,
+            CullKey::Pareto => -(self.score.pareto_front as f64),
+            CullKey::Novelty => self.score.hybrid_fitness,
+        }
+    }
+}
+
+impl BotScore {
+    fn new(playoff_rounds: usize) -> BotScore {
+        BotScore {
+            wins: vec![0; playoff_rounds],
+            ..Default::default()
+        }
+    }
+
+    /// Fitness divided by the number of games, comparable between
+    /// iterations that played different numbers of games
+    fn fitness_per_game(&self) -> f64 {
+        self.fitness / self.metrics.games.max(1) as f64
+    }
+
+    /// Adds a game to the metric totals and its weighted fitness
+    fn add_game(&mut self, metrics: &GameMetrics, weights: &FitnessWeights) {
+        self.fitness += weights.fitness(metrics);
+        self.metrics.add(metrics);
+    }
 }
 
 #[async_trait::async_trait]
@@ -75,24 +250,37 @@ impl RobotRunner for &mut Bot {
     }
 }
 
+/// Keeps at most `target_species` species, dropping the ones whose best bot
+/// ranks lowest by `cull_by`, then removes bots from random species, picked
+/// by `selection` from the worst end, until `target_bots` are left. Expects
+/// `bots` sorted best first, the first bot of a species is never removed.
 fn cull_bots<RNG: rand::Rng>(
     bots: Vec<Bot>,
     target_species: usize,
     target_bots: usize,
+    min_bots_per_species: usize,
+    cull_by: CullKey,
+    selection: Selection,
     rng: &mut RNG,
 ) -> Vec<Bot> {
-    let mut species = HashMap::new();
-    let mut species_scores = HashMap::new();
+    // BTreeMaps so the order species are visited in, and with it the random
+    // choices below, is the same every run
+    let mut species = BTreeMap::new();
+    let mut species_scores = BTreeMap::new();
 
-    let get_remaining_bots = |species: &HashMap<Species, Vec<Bot>>| {
+    let get_remaining_bots = |species: &BTreeMap<Species, Vec<Bot>>| {
         species.values().map(|k: &Vec<Bot>| k.len()).sum::<usize>()
     };
 
     for bot in bots.into_iter() {
-        let k: &mut BotScore = species_scores
-            .entry(bot.species)
-            .or_insert(Default::default());
-        *k = (*k).max(bot.score);
+        let key = bot.rank(cull_by);
+        match species_scores.get_mut(&bot.species) {
+            Some(best) if key > *best => *best = key,
+            Some(_) => {}
+            None => {
+                species_scores.insert(bot.species, key);
+            }
+        }
         species.entry(bot.species).or_insert(vec![]).push(bot);
     }
 
@@ -114,17 +302,19 @@ fn cull_bots<RNG: rand::Rng>(
     while get_remaining_bots(&species) > target_bots {
         let mut candidates = species
             .values_mut()
-            .filter(|d| d.len() >= MIN_BOTS_PER_SPECIES + 1)
+            .filter(|d| d.len() >= min_bots_per_species + 1)
             .collect::<Vec<_>>();
         if candidates.len() == 0 {
             break;
         }
         let length = candidates.len();
         if let Some(candiate) = candidates.get_mut(rng.gen_range(0..length)) {
-            candiate.swap_remove(
-                rng.gen_range(1..candiate.len())
-                    .max(rng.gen_range(1..candiate.len())),
-            );
+            // the species' best bot is never a candidate
+            let fitness = candiate[1..]
+                .iter()
+                .map(|bot| bot.selection_fitness(cull_by))
+                .collect::<Vec<_>>();
+            candiate.remove(1 + selection.pick_worst(&fitness, rng));
         }
     }
 
@@ -149,9 +339,43 @@ fn crossover<RNG: rand::Rng>(bots: &[Bot], rng: &mut RNG) -> Bot {
         score: Default::default(),
         generation: 0,
         parents: Some([first_species, bots[next_bot_index].species]),
+        rating: Default::default(),
+        island: 0,
+        age: bots[0].age.max(bots[next_bot_index].age),
     };
 }
 
+/// Replaces every bot whose genome is structurally identical to an earlier one
+/// with a fresh mutant of itself. Returns the number of unique genomes that
+/// were present before replacement.
+fn replace_duplicate_bots<RNG: rand::Rng>(bots: &mut [Bot], rng: &mut RNG) -> usize {
+    const MAX_ATTEMPTS: usize = 10;
+
+    let mut seen = HashSet::with_capacity(bots.len());
+    let mut unique = 0;
+
+    for bot in bots.iter_mut() {
+        if seen.insert(bot.logic.structural_hash()) {
+            unique += 1;
+            continue;
+        }
+
+        for _attempt in 0..MAX_ATTEMPTS {
+            let mut mutant = bot.logic.clone();
+            mutant.mutate(rng, true);
+            let mutant = mutant.simplify();
+
+            if seen.insert(mutant.structural_hash()) {
+                bot.logic = mutant;
+                bot.generation += 1;
+                break;
+            }
+        }
+    }
+
+    unique
+}
+
 fn draw_game(game: &MainOutput) -> Vec<String> {
     let mut result = Vec::with_capacity(100);
 
@@ -186,7 +410,12 @@ fn draw_game(game: &MainOutput) -> Vec<String> {
     result
 }
 
-async fn run_game(bots: &mut [Bot], blue_index: usize, red_index: usize) -> MainOutput {
+async fn run_game(
+    bots: &mut [Bot],
+    blue_index: usize,
+    red_index: usize,
+    seed: Option<String>,
+) -> MainOutput {
     assert!(blue_index < bots.len());
     assert!(red_index < bots.len());
     assert!(red_index != blue_index);
@@ -206,180 +435,358 @@ async fn run_game(bots: &mut [Bot], blue_index: usize, red_index: usize) -> Main
     let result = logic::run(
         runners,
         |_| (),
-        100,
+        GAME_LENGTH,
         true,
         None,
         logic::GameMode::Normal,
-        None,
+        seed,
     )
     .await;
 
     result
 }
 
-fn run_batch<'a>(bots: &'a mut [Bot], iteration: usize) -> BoxFuture<'a, ()> {
-    const MAX_SCORE: usize = 4 * NUMER_OF_GAMES_PER_BOT_PER_ROUND;
+fn runtime(config: &Config) -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_multi_thread()
+        .enable_all()
+        .max_blocking_threads(config.worker_threads())
+        .build()
+        .expect("failed to start the tokio runtime")
+}
 
-    async move {
-        if bots.len() == 0 {
-            return;
+fn main() {
+    let command = match Command::from_args(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(e) => {
+            eprintln!("{e}\n\n{}", cli::USAGE);
+            std::process::exit(2);
         }
+    };
 
-        for i in 0..bots.len() {
-            for offset in 1..=NUMER_OF_GAMES_PER_BOT_PER_ROUND {
-                let bot_blue_index = i;
-                let bot_red_index = (i + offset) % bots.len();
-
-                if bot_blue_index == bot_red_index {
-                    continue;
-                }
-
-                let result = run_game(bots, bot_blue_index, bot_red_index).await;
-
-                match result.winner {
-                    None => {
-                        bots[bot_blue_index].score.wins[iteration] += 1;
-                        bots[bot_red_index].score.wins[iteration] += 1;
-                    }
-                    Some(logic::Team::Blue) => {
-                        bots[bot_blue_index].score.wins[iteration] += 2;
-                    }
-                    Some(logic::Team::Red) => {
-                        bots[bot_red_index].score.wins[iteration] += 2;
-                    }
-                }
+    let result = match command {
+        Command::Train(config) => {
+            runtime(&config).block_on(train(config));
+            Ok(())
+        }
+        Command::Play { config, blue, red } => {
+            runtime(&config).block_on(commands::play(&config, &blue, &red))
+        }
+        Command::Export {
+            config,
+            bot,
+            format,
+        } => commands::export(&config, &bot, format),
+        Command::Inspect { save } => commands::inspect(&save),
+        Command::ExportElites {
+            config,
+            directory,
+            format,
+        } => commands::export_elites(&config, &directory, format),
+    };
 
-                let (total_red_health, total_blue_health, total_red_units, total_blue_units) =
-                    result.turns[result.turns.len() - 1]
-                        .state
-                        .objs
-                        .values()
-                        .fold(
-                            (0, 0, 0, 0),
-                            |(red_health, blue_health, red_units, blue_units), b| match b.1 {
-                                ObjDetails::Unit(Unit { team, health, .. }) => match team {
-                                    Team::Red => (
-                                        red_health + health as isize,
-                                        blue_health,
-                                        red_units + 1,
-                                        blue_units,
-                                    ),
-                                    Team::Blue => (
-                                        red_health,
-                                        blue_health + health as isize,
-                                        red_units,
-                                        blue_units + 1,
-                                    ),
-                                },
-                                _ => (red_health, blue_health, red_units, blue_units),
-                            },
-                        );
-                match result.winner {
-                    Some(Team::Red) => assert!(total_red_units > total_blue_units),
-                    Some(Team::Blue) => assert!(total_blue_units > total_red_units),
-                    None => assert_eq!(total_red_units, total_blue_units),
-                }
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
 
-                bots[bot_blue_index].score.friendly_units += total_blue_units;
-                bots[bot_blue_index].score.enemy_units -= total_red_units;
-                bots[bot_red_index].score.friendly_units += total_red_units;
-                bots[bot_red_index].score.enemy_units -= total_blue_units;
+/// Plays an island's games and leaves it sorted best first. With
+/// `opponents` the bots play those instead of each other. Everything the
+/// games change outside of `bots` is collected in `evaluation`, so all
+/// islands can be evaluated at once.
+async fn evaluate_island(
+    bots: &mut [Bot],
+    tournament: &dyn Tournament,
+    opponents: Option<&[Bot]>,
+    hall_of_fame: &HallOfFame,
+    novelty_archive: &NoveltyArchive,
+    evaluation: &mut Evaluation<'_>,
+) {
+    let config = evaluation.config;
+    for bot in bots.iter_mut() {
+        bot.score = BotScore::new(config.playoff_rounds);
+    }
 
-                bots[bot_blue_index].score.friendly_health += total_blue_health;
-                bots[bot_blue_index].score.enemy_health -= total_red_health;
-                bots[bot_red_index].score.enemy_health += total_red_health;
-                bots[bot_blue_index].score.enemy_health -= total_blue_health;
-            }
-        }
+    bots.shuffle(&mut evaluation.rng);
 
-        for bot in bots.iter_mut() {
-            bot.score.wins[iteration] /= MAX_SCORE / 5;
+    play_hall_of_fame(bots, hall_of_fame, evaluation).await;
+    match opponents {
+        Some(opponents) => {
+            coevolution::play_opponents(bots, opponents, config.coevolution_games, evaluation)
+                .await
         }
+        None => tournament.run(bots, evaluation).await,
+    }
+    novelty::rank(
+        bots,
+        novelty_archive,
+        config.novelty_neighbours,
+        config.novelty_weight,
+    );
+    // the non-dominated sort is quadratic in the island size, only pay for
+    // it when culling uses it
+    if config.cull_by == CullKey::Pareto {
+        pareto::rank(bots);
+    }
+    match config.cull_by {
+        CullKey::Score => {}
+        CullKey::Rating => bots.sort_by_key(|bot| OrderedFloat(bot.rating.0)),
+        CullKey::Pareto => bots.sort_by_key(ParetoRank::of),
+        CullKey::Novelty => bots.sort_by_key(NoveltyRank::of),
+    }
 
-        bots.sort_by_key(|t| (t.score.wins[iteration], t.score));
+    bots.reverse();
+}
 
-        if iteration < NUMER_OF_PLAYOFF_ROUNDS - 1 {
-            let segment_size = bots.len() / 3;
-            assert!(
-                segment_size > NUMER_OF_GAMES_PER_BOT_PER_ROUND,
-                "segment size is {segment_size}"
-            );
+/// Culls an island down to `surviving_robots` and adds a crossover every
+/// `crossover_interval` iterations, if the island holds two species to cross.
+fn cull_island<RNG: rand::Rng>(bots: &mut Vec<Bot>, config: &Config, iteration: usize, rng: &mut RNG) {
+    let culled_length = config.surviving_robots;
+    let population = std::mem::take(bots);
+    // an ALPS layer can shrink to a single species after promotion
+    let crosses = population.iter().any(|bot| bot.species != population[0].species);
+    if crosses && iteration % config.crossover_interval == config.crossover_interval - 1 {
+        *bots = cull_bots(
+            population,
+            config.num_species - 1,
+            culled_length,
+            config.min_bots_per_species,
+            config.cull_by,
+            config.survivor_selection,
+            rng,
+        );
+        let mut child = crossover(bots, rng);
+        child.island = bots[0].island;
+        bots.push(child);
+    } else {
+        *bots = cull_bots(
+            population,
+            config.num_species,
+            culled_length,
+            config.min_bots_per_species,
+            config.cull_by,
+            config.survivor_selection,
+            rng,
+        );
+    }
+}
 
-            run_batch(&mut bots[0..segment_size], iteration + 1).await;
-            run_batch(&mut bots[segment_size..2 * segment_size], iteration + 1).await;
-            run_batch(&mut bots[2 * segment_size..], iteration + 1).await;
+/// Refills a culled island with mutants, copied from `elites` if given and
+/// otherwise from the survivors. The survivors end up first, best first.
+fn mutate_island<RNG: rand::Rng>(
+    bots: &mut Vec<Bot>,
+    config: &Config,
+    elites: Option<&EliteArchive>,
+    adaptation: &Adaptation,
+    rng: &mut RNG,
+) {
+    // culling groups the bots by species, parents are picked by rank
+    let survivors = config.surviving_robots.min(bots.len());
+    bots[..survivors].sort_by_key(|bot| Reverse(bot.rank(config.cull_by)));
+    let survivor_fitness = bots[..survivors]
+        .iter()
+        .map(|bot| bot.selection_fitness(config.cull_by))
+        .collect::<Vec<_>>();
+
+    while bots.len() < config.num_robots {
+        let mut bot_copy = match elites.and_then(|elites| elites.choose(rng)) {
+            Some(elite) => Bot {
+                island: bots[0].island,
+                ..elite.clone()
+            },
+            None => bots[config.parent_selection.pick(&survivor_fitness, rng)].clone(),
+        };
+        bot_copy.generation += 1;
+        for _ in 0..adaptation.mutations(rng) {
+            bot_copy.logic.mutate(rng, false);
         }
+        bot_copy.logic = bot_copy.logic.simplify().simplify().simplify();
+        bots.push(bot_copy);
     }
-    .boxed()
 }
 
-#[tokio::main]
-async fn main() {
+async fn train(mut config: Config) {
     // println!("{:?}", std::fs::read_dir(".").unwrap().collect::<Vec<_>>());
-    let mut rng = rand::thread_rng();
-
-    let mut family_tree = FamilyTree::new();
-
-    let mut bots = if Path::new(SAVE_FILENAME).exists() {
-        let file = OpenOptions::new().read(true).open(SAVE_FILENAME).unwrap();
-        ciborium::from_reader(file).unwrap()
-    } else {
-        let mut bots = (0..NUM_ROBOTS - 1)
-            .map(|_| generate_bot(&mut rng))
-            .collect::<Vec<_>>();
-
-        bots.push(Bot {
-            species: Species(0),
-            logic: Expression::new(expression::ExpressionKind::If {
-                    condition: Expression::new_box(
-                        expression::ExpressionKind::GreaterThan {
-                            left: Expression::new_box(
-                                expression::ExpressionKind::X,
-                            ),
-                            right: Expression::new_box(expression::ExpressionKind::ConstantNumber(9),
-                            ),
-                        },
-                    ),
-                    then: Expression::new_box(expression::ExpressionKind::ConstantMove(Move::Move(Direction::West))),
-                    otherwise: Expression::new_box(expression::ExpressionKind::ConstantMove(Move::Move(Direction::East))),
-                }
-            ),
-            score: Default::default(),
-            generation: 0,
-            parents: None,
-        });
-        bots
+    let checkpoint = match Checkpoint::load_latest(&config) {
+        Some(checkpoint) => {
+            if config.seed.is_some_and(|requested| requested != checkpoint.seed) {
+                eprintln!("seed ignored, continuing the saved run");
+            }
+            let saved_config = Config {
+                seed: config.seed,
+                ..checkpoint.config.clone()
+            };
+            if saved_config != config {
+                eprintln!("configuration differs from the checkpoint, using the new one");
+            }
+            checkpoint
+        }
+        None => {
+            let seed = config.seed.unwrap_or_else(|| rand::thread_rng().gen::<u64>());
+            let mut rng = ChaCha8Rng::seed_from_u64(seed);
+            let bots = initial_population(&config, &mut rng);
+            Checkpoint {
+                format_version: FORMAT_VERSION,
+                iteration: 0,
+                seed,
+                config: config.clone(),
+                rng,
+                family_tree: FamilyTree::new(),
+                hall_of_fame: HallOfFame::default(),
+                species_ratings: SpeciesRatings::default(),
+                population_champions: vec![],
+                novelty_archive: NoveltyArchive::default(),
+                elites: EliteArchive::default(),
+                adaptation: Adaptation::default(),
+                games_played: 0,
+                bots,
+            }
+        }
     };
+    let Checkpoint {
+        iteration: start_iteration,
+        seed,
+        mut rng,
+        mut family_tree,
+        mut hall_of_fame,
+        mut species_ratings,
+        mut population_champions,
+        mut novelty_archive,
+        mut elites,
+        mut adaptation,
+        games_played,
+        bots,
+        ..
+    } = checkpoint;
+    if start_iteration > 0 {
+        println!(
+            "Resuming at iteration {start_iteration}, {} species in the family tree",
+            family_tree.len()
+        );
+    }
+    println!("Seed: {seed}");
+    config.seed = Some(seed);
+    config.write_effective();
 
-    let (channel, _) = tokio::sync::broadcast::channel::<TrainingProgressAnnouncement>(16);
-    tokio::spawn(start_socket(channel.clone()));
+    let tournament = tournament::tournament(config.tournament);
+    let cache_file = config.run_file("cache.cbor");
+    let mut cache = GameCache::load(&cache_file, config.cache_games);
 
-    for i in 0.. {
-        for bot in bots.iter_mut() {
-            bot.score = Default::default();
+    let (channel, _) = tokio::sync::broadcast::channel::<TrainingProgressAnnouncement>(16);
+    let control = TrainingControl::new(config.run_file("pause"));
+    control::listen_for_signals(control.clone());
+    let socket = tokio::spawn(start_socket(
+        config.bind_address.clone(),
+        channel.clone(),
+        control.clone(),
+    ));
+
+    let mut islands = island::split(bots, config.islands);
+    for (index, island) in islands.iter_mut().enumerate() {
+        if island.is_empty() {
+            *island = initial_population(&config, &mut rng);
+            for bot in island.iter_mut() {
+                bot.island = index;
+            }
         }
+    }
+    // `play_matches` merges usage counters in the node order of the
+    // canonical form
+    for bot in islands.iter_mut().flatten() {
+        bot.logic = bot.logic.canonical();
+    }
+    population_champions.resize(config.islands, HallOfFame::default());
+    elites.configure(&config);
 
-        bots.shuffle(&mut rng);
+    let mut progress = Progress::new(start_iteration, games_played, adaptation.run_stagnant_iterations);
+    let mut stop_reason = progress.check(&config);
+    let mut champion = None;
+    // benchmark win rate of the last measured champion, by structural hash
+    let mut champion_win_rate: Option<(u64, f64)> = None;
 
-        let global_start_time = Instant::now();
+    for i in start_iteration.. {
+        if stop_reason.is_some() {
+            break;
+        }
+        control.wait_while_paused().await;
+        if control.stop_requested() {
+            stop_reason = Some(StopReason::Requested);
+            break;
+        }
 
-        run_batch(&mut bots[..], 0).await;
+        let global_start_time = Instant::now();
 
-        println!("\tWins:\t {:?}", bots[bots.len() - 1].score);
+        cache.reset_statistics();
+        let mut benchmark_games = 0;
+        let opponents = config.coevolution.then(|| {
+            (0..islands.len())
+                .map(|population| coevolution::opponents(&islands, population))
+                .collect::<Vec<_>>()
+        });
+        // every island gets its own random stream, drawn in island order so
+        // the run stays reproducible
+        let mut evaluations = (0..islands.len())
+            .map(|_| Evaluation::new(&cache, &config, seed, ChaCha8Rng::seed_from_u64(rng.gen())))
+            .collect::<Vec<_>>();
+        join_all(islands.iter_mut().zip(evaluations.iter_mut()).enumerate().map(
+            |(index, (bots, evaluation))| {
+                evaluate_island(
+                    bots,
+                    tournament.as_ref(),
+                    opponents.as_ref().map(|opponents| opponents[index].as_slice()),
+                    &hall_of_fame,
+                    &novelty_archive,
+                    evaluation,
+                )
+            },
+        ))
+        .await;
+        let finished = evaluations
+            .into_iter()
+            .map(|evaluation| {
+                (
+                    evaluation.cache.finish(),
+                    evaluation.species_games,
+                    evaluation.side_bias,
+                )
+            })
+            .collect::<Vec<_>>();
+        let mut side_bias = SideBias::default();
+        // champion histories are played after the evaluation timing stops,
+        // the metrics only count the games up to here
+        let mut evaluation_games = 0;
+        let mut evaluation_games_played = 0;
+        for (updates, species_games, island_side_bias) in finished {
+            evaluation_games += updates.hits + updates.misses;
+            evaluation_games_played += updates.misses;
+            cache.merge(updates);
+            species_ratings.apply(&species_games, config.elo_k_factor);
+            side_bias.add(&island_side_bias);
+        }
+        let mut timings = Timings {
+            evaluation: global_start_time.elapsed(),
+            ..Default::default()
+        };
+        let population_stats = PopulationStats::new(islands.iter().flatten());
+
+        let champion_island = island::champion_island(&islands, config.cull_by);
+        let best_bot = islands[champion_island][0].clone();
+        champion = Some(best_bot.clone());
+
+        println!("\tWins:\t {:?}", best_bot.score);
+        println!("\tRating:\t {}", best_bot.rating);
         println!(
             "\tSpecies:\t {:} (generation {})",
-            bots[bots.len() - 1].species,
-            bots[bots.len() - 1].generation
+            best_bot.species,
+            best_bot.generation
         );
-        if let Some([parent1, parent2]) = bots[bots.len() - 1].parents {
+        if let Some([parent1, parent2]) = best_bot.parents {
             println!("\tParents:\t{parent1}\t{parent2}")
         }
-        println!("\tLogic:\t {}", bots[bots.len() - 1].logic);
+        println!("\tLogic:\t {}", best_bot.logic);
 
         let global_end_time = Instant::now();
 
-        bots.reverse();
-
         let mut file = OpenOptions::new()
             .write(true)
             .truncate(true)
@@ -391,83 +798,263 @@ async fn main() {
             "#{}
             #{}
             {}",
-            serde_json::to_string(&bots[0].logic).unwrap(),
-            bots[0].species,
-            bots[0].logic
+            serde_json::to_string(&best_bot.logic).unwrap(),
+            best_bot.species,
+            best_bot.logic
         )
         .unwrap();
 
-        let mut species = HashMap::new();
-        for bot in bots.iter() {
-            let length = species.len();
-
-            species.entry(bot.species).or_insert(
-                [
-                    '-', '#', '*', '&', '$', '0', '1', '2', '3', '4', '5', '6', '7', '8', '9',
-                ]
-                .get(length)
-                .unwrap_or(&'_'),
+        let island_summaries = islands
+            .iter()
+            .enumerate()
+            .map(|(index, bots)| island::IslandSummary::new(index, bots))
+            .collect::<Vec<_>>();
+        for (bots, summary) in islands.iter().zip(&island_summaries) {
+            println!("\t[{}]", island::species_strip(bots, 100));
+            if islands.len() > 1 {
+                println!("\tIsland {}:\t {summary}", summary.island);
+            }
+        }
+        if config.cull_by == CullKey::Pareto {
+            pareto::report(&islands[champion_island], 5);
+        }
+        for bots in islands.iter() {
+            novelty_archive.add_most_novel(
+                bots,
+                config.novelty_archive_additions,
+                config.novelty_archive_size,
             );
         }
-
-        print!("\t[");
-        for i in 0..100.min(NUM_ROBOTS) {
-            print!("{}", species.get(&bots[i].species).unwrap_or(&&'_'))
+        if config.map_elites {
+            let (improved, games) = elites
+                .insert(&islands.concat(), i, config.benchmark_games, seed)
+                .await;
+            benchmark_games += games;
+            println!(
+                "\tElites:\t {}/{} cells, {improved} improved, best win rate {:.2}",
+                elites.len(),
+                elites.cells(),
+                elites.best().map_or(0.0, |elite| elite.win_rate)
+            );
         }
-        println!("]");
+        println!(
+            "\tNovelty:\t {:.3} ({}), {} archived",
+            best_bot.score.novelty,
+            novelty::descriptor(&best_bot),
+            novelty_archive.len()
+        );
+        if config.coevolution {
+            let champions = islands.iter().map(|bots| bots[0].clone()).collect::<Vec<_>>();
+            for (history, champion) in population_champions.iter_mut().zip(&champions) {
+                history.add(champion, i, config.hall_of_fame_size);
+            }
+            let results =
+                coevolution::play_champion_histories(&champions, &population_champions, &mut cache, seed)
+                    .await;
+            for (population, result) in results.iter().enumerate() {
+                println!(
+                    "\tIsland {population} champion:\t {:.1}% against {} past champions of the others",
+                    100.0 * result.win_rate(),
+                    (result.wins + result.draws + result.losses) / 2
+                );
+            }
+        }
+        println!("\tSides:\t {side_bias}");
+        println!(
+            "\tCache:\t {} hits, {} misses, {} stored",
+            cache.hits,
+            cache.misses,
+            cache.len()
+        );
         println!(
             "Iteration {i} took {:?}",
             global_end_time - global_start_time
         );
 
-        family_tree.analize(&bots, i);
+        family_tree.analize(&islands.concat(), i);
+        if hall_of_fame.add(&best_bot, i, config.hall_of_fame_size) {
+            println!("\tHall of fame:\t {} champions", hall_of_fame.len());
+        }
+        let champion_hash = best_bot.logic.structural_hash();
+        if config.benchmark_interval > 0 && i % config.benchmark_interval == 0 {
+            let results = benchmark::benchmark(&best_bot.logic, config.benchmark_games, seed).await;
+            benchmark_games += config.benchmark_games * results.len();
+            benchmark::record(&config, i, &results);
+            champion_win_rate = Some((champion_hash, benchmark::mean_win_rate(&results)));
+            progress.benchmark = Some((i, results));
+        }
 
-        let best_bot = bots[0].clone();
+        // fitness only compares the champion to the current population, which
+        // improves along with it, the benchmark shows how far the run has come
+        if config.measures_progress() {
+            let win_rate = match champion_win_rate {
+                Some((hash, win_rate)) if hash == champion_hash => win_rate,
+                _ => {
+                    let results =
+                        benchmark::benchmark(&best_bot.logic, config.benchmark_games, seed).await;
+                    benchmark_games += config.benchmark_games * results.len();
+                    let win_rate = benchmark::mean_win_rate(&results);
+                    champion_win_rate = Some((champion_hash, win_rate));
+                    win_rate
+                }
+            };
+            adaptation.record(win_rate, &config);
+        }
+        println!(
+            "\tMutation:\t strength {:.2}, {} iterations without a better champion",
+            adaptation.mutation_strength, adaptation.stagnant_iterations
+        );
 
-        let culled_length = SURVIVING_ROBOTS;
-        if i % CROSSOVER_INTERVAL == CROSSOVER_INTERVAL - 1 {
-            bots = cull_bots(bots, NUM_SPECIES - 1, culled_length, &mut rng);
-            bots.push(crossover(&bots, &mut rng));
-        } else {
-            bots = cull_bots(bots, NUM_SPECIES, culled_length, &mut rng);
+        if config.alps {
+            let promoted = alps::promote(&mut islands, config.alps_age_gap, &mut rng);
+            alps::report(&islands, config.alps_age_gap);
+            println!("\tPromoted:\t {promoted} bots");
+        }
+
+        let culling_start_time = Instant::now();
+        for bots in islands.iter_mut() {
+            cull_island(bots, &config, i, &mut rng);
+        }
+        timings.culling = culling_start_time.elapsed();
+
+        let mutation_start_time = Instant::now();
+        for bots in islands.iter_mut() {
+            let elites = config.map_elites.then_some(&elites);
+            mutate_island(bots, &config, elites, &adaptation, &mut rng);
+        }
+        timings.mutation = mutation_start_time.elapsed();
+
+        if adaptation.stagnated(&config) {
+            let mut replaced = 0;
+            for bots in islands.iter_mut() {
+                replaced += adaptation::restart_weakest_species(
+                    bots,
+                    config.restart_species,
+                    &config,
+                    &mut rng,
+                );
+            }
+            adaptation.restarted();
+            println!("\tRestart:\t replaced {replaced} bots of the weakest species");
         }
 
         if channel.receiver_count() > 0 {
-            let last_game_status = run_game(bots.as_mut_slice(), 0, 1).await;
+            // played on copies so watching does not change the bots' usage counters
+            let mut preview = islands[champion_island][0..2].to_vec();
+            let preview_seed = GameKey::new(&preview[0].logic, &preview[1].logic, seed).seed;
+            let last_game_status = run_game(&mut preview, 0, 1, Some(preview_seed)).await;
 
             channel
                 .send(TrainingProgressAnnouncement {
                     best_bot,
                     species: family_tree.clone(),
                     iteration_number: i,
-                    last_game: draw_game(&last_game_status)
+                    last_game: draw_game(&last_game_status),
+                    islands: island_summaries,
                 })
                 .unwrap();
         }
 
-        while bots.len() < NUM_ROBOTS {
-            let mut bot_copy = bots[rng
-                .gen_range(0..culled_length)
-                .min(rng.gen_range(0..culled_length))]
-            .clone();
-            bot_copy.generation += 1;
-            bot_copy.logic.mutate(&mut rng, false);
-            bot_copy.logic = bot_copy.logic.simplify().simplify().simplify();
-            bots.push(bot_copy);
+        if config.alps {
+            alps::grow_older(&mut islands);
+            if (i + 1) % config.alps_age_gap == 0 {
+                let moved = alps::renew_bottom_layer(&mut islands, &mut rng);
+                println!("\tRenewed:\t bottom layer, {moved} bots moved up");
+            }
+        } else if !config.coevolution
+            && config.migration_interval > 0
+            && (i + 1) % config.migration_interval == 0
+        {
+            let moved = island::migrate(
+                &mut islands,
+                config.migration_topology,
+                config.migrants,
+                &mut rng,
+            );
+            if moved > 0 {
+                println!("\tMigration:\t {moved} bots moved");
+            }
+        }
+
+        let mut unique_genomes = 0;
+        for bots in islands.iter_mut() {
+            unique_genomes += replace_duplicate_bots(bots, &mut rng);
+        }
+        // also resets `times_used` for the next evaluation
+        for bot in islands.iter_mut().flatten() {
+            bot.logic = bot.logic.canonical();
         }
+        let bots = islands.concat();
+        println!("\tUnique genomes:\t {unique_genomes}/{}", bots.len());
 
-        for bot in bots.iter_mut() {
-            bot.logic.clear_times_used();
+        species_ratings.retain_population(&bots);
+        let best_species = species_ratings
+            .best(3)
+            .into_iter()
+            .map(|(species, rating)| format!("{species} {rating}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!("\tSpecies ratings:\t {best_species}");
+
+        timings.iteration = global_start_time.elapsed();
+        let metrics = IterationMetrics::new(
+            i,
+            &population_stats,
+            unique_genomes,
+            evaluation_games,
+            evaluation_games_played,
+            &timings,
+        );
+        metrics::record(&config, &metrics);
+
+        progress.iterations = i + 1;
+        // cache hits replay nothing, only games that ran count
+        progress.games_played += cache.misses + benchmark_games;
+        progress.stagnant_iterations = adaptation.run_stagnant_iterations;
+
+        let checkpoint = Checkpoint {
+            format_version: FORMAT_VERSION,
+            iteration: i + 1,
+            seed,
+            config: config.clone(),
+            rng: rng.clone(),
+            family_tree: family_tree.clone(),
+            hall_of_fame: hall_of_fame.clone(),
+            species_ratings: species_ratings.clone(),
+            population_champions: population_champions.clone(),
+            novelty_archive: novelty_archive.clone(),
+            elites: elites.clone(),
+            adaptation: adaptation.clone(),
+            games_played: progress.games_played,
+            bots: bots.clone(),
+        };
+        if let Err(e) = checkpoint.save() {
+            eprintln!("failed to save checkpoint: {e}");
         }
 
-        if let Ok(save) = OpenOptions::new().write(true).truncate(true).create(true).open(SAVE_FILENAME) {
-            ciborium::into_writer(&bots, save).unwrap();
+        let champion_hashes = population_champions.iter().flat_map(HallOfFame::hashes);
+        cache.retain_population(&bots, hall_of_fame.hashes().chain(champion_hashes));
+        cache.save(&cache_file);
+
+        stop_reason = if control.stop_requested() {
+            Some(StopReason::Requested)
         } else {
-            eprintln!("failed to save file");
+            progress.check(&config)
+        };
+        if let Some(reason) = stop_reason {
+            println!("Stopped after iteration {i} ({reason}), saved to {}", config.save_file);
         }
+    }
 
+    if let (Some(reason), Some(champion)) = (stop_reason, champion) {
+        termination::finish(&config, reason, &champion, &progress, hall_of_fame.len(), seed).await;
+    } else if let Some(reason) = stop_reason {
+        println!("Stopped before the first iteration: {reason}");
     }
 
+    control.shutdown();
+    let _ = tokio::time::timeout(std::time::Duration::from_secs(5), socket).await;
+
     // println!("{:?}", std::fs::read_dir("..").unwrap().collect::<Vec<_>>());
     // println!(
     //     "{:?}",
@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt::Write;
+use std::path::Path;
+
+use futures::future::join_all;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::benchmark::{self, Reference};
+use crate::cli::ExportFormat;
+use crate::commands::export_bot;
+use crate::config::{BehaviourDimension, Config};
+use crate::novelty::{self, Behaviour};
+use crate::ordered_float::OrderedFloat;
+use crate::Bot;
+
+/// The best bot found so far in a cell of the behaviour grid.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Elite {
+    pub cell: (usize, usize),
+    pub bot: Bot,
+    pub behaviour: Behaviour,
+    /// Mean win rate against the benchmark references. Unlike the fitness it
+    /// does not depend on the population the bot was evaluated in, so elites
+    /// from different iterations compare fairly.
+    pub win_rate: f64,
+    /// Iteration the bot was evaluated in
+    pub iteration: usize,
+}
+
+/// MAP-Elites: a grid over two behaviour dimensions keeping the best bot of
+/// every cell, a library of different strategies that are each good at
+/// their own style of play.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+pub struct EliteArchive {
+    dimensions: Option<[BehaviourDimension; 2]>,
+    bins: usize,
+    elites: Vec<Elite>,
+    /// Win rates by `structural_hash` of the elites and the last challengers,
+    /// the benchmark plays the same maps every time so a genome that did not
+    /// change is only measured once
+    win_rates: BTreeMap<u64, f64>,
+}
+
+impl EliteArchive {
+    /// Takes the grid from `config`. If it changed since the archive was
+    /// saved, the elites are sorted into the new cells, keeping the best of
+    /// each.
+    pub fn configure(&mut self, config: &Config) {
+        let dimensions = Some(config.map_elites_dimensions);
+        if self.dimensions == dimensions && self.bins == config.map_elites_bins {
+            return;
+        }
+
+        self.dimensions = dimensions;
+        self.bins = config.map_elites_bins;
+        for elite in std::mem::take(&mut self.elites) {
+            self.offer(elite);
+        }
+    }
+
+    fn cell(&self, behaviour: &Behaviour) -> (usize, usize) {
+        let dimensions = self.dimensions.expect("archive is not configured");
+        let bin = |dimension| {
+            let value = behaviour.value(dimension).clamp(0.0, 1.0);
+            ((value * self.bins as f64) as usize).min(self.bins - 1)
+        };
+        (bin(dimensions[0]), bin(dimensions[1]))
+    }
+
+    /// Keeps `elite` if its cell is empty or holds a worse bot
+    fn offer(&mut self, mut elite: Elite) -> bool {
+        elite.cell = self.cell(&elite.behaviour);
+        match self
+            .elites
+            .iter_mut()
+            .find(|other| other.cell == elite.cell)
+        {
+            Some(other) if other.win_rate >= elite.win_rate => false,
+            Some(other) => {
+                *other = elite;
+                true
+            }
+            None => {
+                self.elites.push(elite);
+                true
+            }
+        }
+    }
+
+    /// Offers the best bot of every cell this iteration reached. Genomes
+    /// without a known win rate are benchmarked with `games` per reference,
+    /// on the same maps every time. Returns how many cells were filled or
+    /// improved and how many games were played.
+    pub async fn insert(
+        &mut self,
+        bots: &[Bot],
+        iteration: usize,
+        games: usize,
+        master_seed: u64,
+    ) -> (usize, usize) {
+        // fitness only compares bots of the same iteration, enough to pick
+        // one challenger per cell
+        let mut challengers: Vec<Elite> = vec![];
+        for bot in bots {
+            let behaviour = novelty::descriptor(bot);
+            let cell = self.cell(&behaviour);
+            let challenger = Elite {
+                cell,
+                bot: bot.clone(),
+                behaviour,
+                win_rate: 0.0,
+                iteration,
+            };
+            let quality = bot.score.fitness_per_game();
+            match challengers.iter_mut().find(|elite| elite.cell == cell) {
+                Some(elite) if elite.bot.score.fitness_per_game() >= quality => {}
+                Some(elite) => *elite = challenger,
+                None => challengers.push(challenger),
+            }
+        }
+
+        let hashes = challengers
+            .iter()
+            .map(|elite| elite.bot.logic.structural_hash())
+            .collect::<Vec<_>>();
+        let mut seen = HashSet::new();
+        let unmeasured = challengers
+            .iter()
+            .zip(&hashes)
+            .filter(|&(_, hash)| !self.win_rates.contains_key(hash) && seen.insert(*hash))
+            .collect::<Vec<_>>();
+        let measured = join_all(
+            unmeasured
+                .iter()
+                .map(|(elite, _)| benchmark::benchmark(&elite.bot.logic, games, master_seed)),
+        )
+        .await;
+        let played = measured.len() * games * Reference::ALL.len();
+        for ((_, &hash), results) in unmeasured.into_iter().zip(measured) {
+            self.win_rates.insert(hash, benchmark::mean_win_rate(&results));
+        }
+
+        let mut improved = 0;
+        for (mut elite, hash) in challengers.into_iter().zip(&hashes) {
+            elite.win_rate = self.win_rates[hash];
+            if self.offer(elite) {
+                improved += 1;
+            }
+        }
+        self.elites.sort_by_key(|elite| elite.cell);
+
+        let keep = self
+            .elites
+            .iter()
+            .map(|elite| elite.bot.logic.structural_hash())
+            .chain(hashes)
+            .collect::<HashSet<_>>();
+        self.win_rates.retain(|hash, _| keep.contains(hash));
+        (improved, played)
+    }
+
+    /// A uniformly picked elite to copy a mutant from
+    pub fn choose<RNG: rand::Rng>(&self, rng: &mut RNG) -> Option<&Bot> {
+        self.elites.choose(rng).map(|elite| &elite.bot)
+    }
+
+    pub fn len(&self) -> usize {
+        self.elites.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.elites.is_empty()
+    }
+
+    pub fn cells(&self) -> usize {
+        self.bins * self.bins
+    }
+
+    pub fn best(&self) -> Option<&Elite> {
+        self.elites
+            .iter()
+            .max_by_key(|elite| OrderedFloat(elite.win_rate))
+    }
+
+    /// Win rate of every cell, the first dimension along the rows
+    fn grid(&self) -> String {
+        let mut grid = String::new();
+        if let Some([rows, columns]) = self.dimensions {
+            writeln!(
+                grid,
+                "rows: {rows:?}, columns: {columns:?}, {} bins",
+                self.bins
+            )
+            .unwrap();
+        }
+        for row in 0..self.bins {
+            for column in 0..self.bins {
+                match self.elites.iter().find(|elite| elite.cell == (row, column)) {
+                    Some(elite) => write!(grid, "{:>8.2}", elite.win_rate).unwrap(),
+                    None => write!(grid, "{:>8}", ".").unwrap(),
+                }
+            }
+            grid.push('\n');
+        }
+        grid
+    }
+
+    /// Writes every elite to `directory` as `elite_<row>_<column>` plus an
+    /// overview of the grid in `grid.txt`.
+    pub fn export(&self, directory: &Path, format: ExportFormat) -> std::io::Result<()> {
+        std::fs::create_dir_all(directory)?;
+        let extension = match format {
+            ExportFormat::Python => "py",
+            ExportFormat::Js => "js",
+            ExportFormat::Json => "json",
+        };
+
+        for elite in &self.elites {
+            let (row, column) = elite.cell;
+            std::fs::write(
+                directory.join(format!("elite_{row}_{column}.{extension}")),
+                export_bot(&elite.bot, format),
+            )?;
+        }
+        std::fs::write(directory.join("grid.txt"), self.grid())
+    }
+}
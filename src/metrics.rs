@@ -0,0 +1,175 @@
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::config::{Config, MetricsFormat};
+use crate::Bot;
+
+/// Fitness and genome statistics of the population right after evaluation
+pub struct PopulationStats {
+    best_fitness: f64,
+    median_fitness: f64,
+    worst_fitness: f64,
+    species: usize,
+    min_genome_size: usize,
+    mean_genome_size: f64,
+    max_genome_size: usize,
+}
+
+impl PopulationStats {
+    pub fn new<'a>(bots: impl IntoIterator<Item = &'a Bot>) -> PopulationStats {
+        let bots = bots.into_iter().collect::<Vec<_>>();
+        let mut fitness = bots
+            .iter()
+            .map(|bot| bot.score.fitness_per_game())
+            .collect::<Vec<_>>();
+        fitness.sort_by(f64::total_cmp);
+        let sizes = bots.iter().map(|bot| bot.logic.size()).collect::<Vec<_>>();
+        let mut species = bots.iter().map(|bot| bot.species).collect::<Vec<_>>();
+        species.sort();
+        species.dedup();
+
+        PopulationStats {
+            best_fitness: fitness.last().copied().unwrap_or(0.0),
+            median_fitness: fitness.get(fitness.len() / 2).copied().unwrap_or(0.0),
+            worst_fitness: fitness.first().copied().unwrap_or(0.0),
+            species: species.len(),
+            min_genome_size: sizes.iter().copied().min().unwrap_or(0),
+            mean_genome_size: sizes.iter().sum::<usize>() as f64 / sizes.len().max(1) as f64,
+            max_genome_size: sizes.iter().copied().max().unwrap_or(0),
+        }
+    }
+}
+
+/// How long the phases of an iteration took
+#[derive(Default)]
+pub struct Timings {
+    pub evaluation: Duration,
+    pub culling: Duration,
+    pub mutation: Duration,
+    pub iteration: Duration,
+}
+
+/// One row of the metrics log. Fitness is per game, see
+/// `BotScore::fitness_per_game`.
+#[derive(Debug, Serialize)]
+pub struct IterationMetrics {
+    pub iteration: usize,
+    pub best_fitness: f64,
+    pub median_fitness: f64,
+    pub worst_fitness: f64,
+    pub species: usize,
+    pub min_genome_size: usize,
+    pub mean_genome_size: f64,
+    pub max_genome_size: usize,
+    pub unique_genomes: usize,
+    /// Games of the evaluation, cache hits included
+    pub games: usize,
+    /// Games the evaluation actually ran per second, without cache hits
+    pub games_per_second: f64,
+    pub evaluation_seconds: f64,
+    pub culling_seconds: f64,
+    pub mutation_seconds: f64,
+    pub iteration_seconds: f64,
+}
+
+impl IterationMetrics {
+    pub fn new(
+        iteration: usize,
+        population: &PopulationStats,
+        unique_genomes: usize,
+        games: usize,
+        games_played: usize,
+        timings: &Timings,
+    ) -> IterationMetrics {
+        let evaluation_seconds = timings.evaluation.as_secs_f64();
+        IterationMetrics {
+            iteration,
+            best_fitness: population.best_fitness,
+            median_fitness: population.median_fitness,
+            worst_fitness: population.worst_fitness,
+            species: population.species,
+            min_genome_size: population.min_genome_size,
+            mean_genome_size: population.mean_genome_size,
+            max_genome_size: population.max_genome_size,
+            unique_genomes,
+            games,
+            games_per_second: games_played as f64 / evaluation_seconds.max(f64::EPSILON),
+            evaluation_seconds,
+            culling_seconds: timings.culling.as_secs_f64(),
+            mutation_seconds: timings.mutation.as_secs_f64(),
+            iteration_seconds: timings.iteration.as_secs_f64(),
+        }
+    }
+
+    fn columns(&self) -> [(&'static str, String); 15] {
+        [
+            ("iteration", self.iteration.to_string()),
+            ("best_fitness", format!("{:.4}", self.best_fitness)),
+            ("median_fitness", format!("{:.4}", self.median_fitness)),
+            ("worst_fitness", format!("{:.4}", self.worst_fitness)),
+            ("species", self.species.to_string()),
+            ("min_genome_size", self.min_genome_size.to_string()),
+            ("mean_genome_size", format!("{:.2}", self.mean_genome_size)),
+            ("max_genome_size", self.max_genome_size.to_string()),
+            ("unique_genomes", self.unique_genomes.to_string()),
+            ("games", self.games.to_string()),
+            ("games_per_second", format!("{:.2}", self.games_per_second)),
+            (
+                "evaluation_seconds",
+                format!("{:.3}", self.evaluation_seconds),
+            ),
+            ("culling_seconds", format!("{:.3}", self.culling_seconds)),
+            ("mutation_seconds", format!("{:.3}", self.mutation_seconds)),
+            (
+                "iteration_seconds",
+                format!("{:.3}", self.iteration_seconds),
+            ),
+        ]
+    }
+}
+
+/// Appends a row to `<save>.metrics.csv` or `<save>.metrics.jsonl`,
+/// depending on `metrics_log`.
+pub fn record(config: &Config, metrics: &IterationMetrics) {
+    let path = match config.metrics_log {
+        MetricsFormat::Off => return,
+        MetricsFormat::Csv => config.run_file("metrics.csv"),
+        MetricsFormat::Jsonl => config.run_file("metrics.jsonl"),
+    };
+
+    match config.metrics_log {
+        MetricsFormat::Csv => {
+            let columns = metrics.columns();
+            let header = columns.iter().map(|(name, _)| *name).collect::<Vec<_>>();
+            let row = columns
+                .into_iter()
+                .map(|(_, value)| value)
+                .collect::<Vec<_>>();
+            append_lines(&path, Some(&header.join(",")), [row.join(",")]);
+        }
+        _ => append_lines(&path, None, [serde_json::to_string(metrics).unwrap()]),
+    }
+}
+
+/// Appends `lines` to the log at `path`, starting a new file with `header`.
+/// Logging is not worth stopping a run for, errors are only printed.
+pub fn append_lines(path: &Path, header: Option<&str>, lines: impl IntoIterator<Item = String>) {
+    let new_file = !path.exists();
+    let write = || -> std::io::Result<()> {
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        if let Some(header) = header.filter(|_| new_file) {
+            writeln!(file, "{header}")?;
+        }
+        for line in lines {
+            writeln!(file, "{line}")?;
+        }
+        Ok(())
+    };
+    if let Err(e) = write() {
+        eprintln!("failed to write {}: {e}", path.display());
+    }
+}
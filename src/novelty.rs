@@ -0,0 +1,223 @@
+use std::collections::HashSet;
+
+use logic::{Coords, MainOutput, ObjDetails, Team};
+use serde::{Deserialize, Serialize};
+
+use crate::config::BehaviourDimension;
+use crate::fitness::GAME_LENGTH;
+use crate::ordered_float::OrderedFloat;
+use crate::Bot;
+
+/// The map is 19 by 19, like `DistanceToCenter` assumes
+const CENTER: Coords = Coords(9, 9);
+/// Manhattan distance from the center to a corner
+const MAX_DISTANCE: f64 = 18.0;
+
+fn manhattan(a: (f64, f64), b: (f64, f64)) -> f64 {
+    (a.0 - b.0).abs() + (a.1 - b.1).abs()
+}
+
+/// How a side played a game, independent of whether it won. Every value is
+/// roughly between 0 and 1 so they weigh the same in `distance`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Behaviour {
+    /// Average distance of the units from the center of the map
+    pub center_distance: f64,
+    /// Average distance of the units from their own centroid
+    pub spread: f64,
+    /// Fraction of unit turns spent next to an enemy, where attacks happen
+    pub engagement: f64,
+    /// Turn a unit first stood next to an enemy, as a fraction of the game
+    pub first_contact: f64,
+}
+
+impl Behaviour {
+    pub fn from_output(output: &MainOutput, team: Team) -> Behaviour {
+        let center = (CENTER.0 as f64, CENTER.1 as f64);
+        let mut unit_turns = 0;
+        let (mut center_distance, mut spread, mut engaged) = (0.0, 0.0, 0);
+        let mut first_contact = None;
+
+        for (turn, state) in output.turns.iter().enumerate() {
+            let mut own = vec![];
+            let mut enemies = HashSet::new();
+            for obj in state.state.objs.values() {
+                if let ObjDetails::Unit(unit) = &obj.1 {
+                    if unit.team == team {
+                        own.push(obj.coords());
+                    } else {
+                        enemies.insert(obj.coords());
+                    }
+                }
+            }
+            if own.is_empty() {
+                continue;
+            }
+
+            let centroid = (
+                own.iter().map(|coords| coords.0 as f64).sum::<f64>() / own.len() as f64,
+                own.iter().map(|coords| coords.1 as f64).sum::<f64>() / own.len() as f64,
+            );
+            for &Coords(x, y) in &own {
+                let position = (x as f64, y as f64);
+                center_distance += manhattan(position, center);
+                spread += manhattan(position, centroid);
+
+                let neighbours = [
+                    Coords(x + 1, y),
+                    Coords(x.wrapping_sub(1), y),
+                    Coords(x, y + 1),
+                    Coords(x, y.wrapping_sub(1)),
+                ];
+                if neighbours.iter().any(|tile| enemies.contains(tile)) {
+                    engaged += 1;
+                    first_contact.get_or_insert(turn);
+                }
+            }
+            unit_turns += own.len();
+        }
+
+        let unit_turns = unit_turns.max(1) as f64;
+        Behaviour {
+            center_distance: center_distance / unit_turns / MAX_DISTANCE,
+            spread: spread / unit_turns / MAX_DISTANCE,
+            engagement: engaged as f64 / unit_turns,
+            first_contact: first_contact.unwrap_or(GAME_LENGTH).min(GAME_LENGTH) as f64
+                / GAME_LENGTH as f64,
+        }
+    }
+
+    fn values(&self) -> [f64; 4] {
+        [
+            self.center_distance,
+            self.spread,
+            self.engagement,
+            self.first_contact,
+        ]
+    }
+
+    pub fn value(&self, dimension: BehaviourDimension) -> f64 {
+        match dimension {
+            BehaviourDimension::CenterDistance => self.center_distance,
+            BehaviourDimension::Spread => self.spread,
+            BehaviourDimension::Engagement => self.engagement,
+            BehaviourDimension::FirstContact => self.first_contact,
+        }
+    }
+
+    pub fn add(&mut self, other: &Behaviour) {
+        self.center_distance += other.center_distance;
+        self.spread += other.spread;
+        self.engagement += other.engagement;
+        self.first_contact += other.first_contact;
+    }
+
+    pub fn scaled(&self, factor: f64) -> Behaviour {
+        Behaviour {
+            center_distance: self.center_distance * factor,
+            spread: self.spread * factor,
+            engagement: self.engagement * factor,
+            first_contact: self.first_contact * factor,
+        }
+    }
+
+    pub fn distance(&self, other: &Behaviour) -> f64 {
+        self.values()
+            .iter()
+            .zip(other.values())
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum::<f64>()
+            .sqrt()
+    }
+}
+
+impl std::fmt::Display for Behaviour {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "center distance {:.2}, spread {:.2}, engagement {:.2}, first contact {:.2}",
+            self.center_distance, self.spread, self.engagement, self.first_contact
+        )
+    }
+}
+
+/// Average behaviour over every game the bot played this iteration
+pub fn descriptor(bot: &Bot) -> Behaviour {
+    let metrics = &bot.score.metrics;
+    metrics.behaviour.scaled(1.0 / metrics.games.max(1) as f64)
+}
+
+/// Behaviours of bots from earlier iterations. Novelty is measured against
+/// them too, so the population can't just move back and forth between two
+/// strategies.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct NoveltyArchive {
+    behaviours: Vec<Behaviour>,
+}
+
+impl NoveltyArchive {
+    /// Archives the `count` most novel bots. Once there are more than
+    /// `max_size` behaviours the oldest ones are dropped.
+    pub fn add_most_novel(&mut self, bots: &[Bot], count: usize, max_size: usize) {
+        let mut by_novelty = bots.iter().collect::<Vec<_>>();
+        by_novelty.sort_by(|a, b| b.score.novelty.total_cmp(&a.score.novelty));
+        self.behaviours
+            .extend(by_novelty.iter().take(count).map(|bot| descriptor(bot)));
+
+        if self.behaviours.len() > max_size {
+            let excess = self.behaviours.len() - max_size;
+            self.behaviours.drain(0..excess);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.behaviours.len()
+    }
+}
+
+/// Stores every bot's novelty, the mean distance of its behaviour to the
+/// `neighbours` nearest ones among the other bots and the archive, and its
+/// hybrid fitness: `novelty_weight` of its novelty rank plus the rest of its
+/// score rank, both scaled to 0 for the worst and 1 for the best bot.
+/// Expects `bots` sorted from worst to best score.
+pub fn rank(bots: &mut [Bot], archive: &NoveltyArchive, neighbours: usize, novelty_weight: f64) {
+    let behaviours = bots.iter().map(descriptor).collect::<Vec<_>>();
+
+    for (index, behaviour) in behaviours.iter().enumerate() {
+        let mut distances = behaviours
+            .iter()
+            .enumerate()
+            .filter(|&(other, _)| other != index)
+            .map(|(_, other)| behaviour.distance(other))
+            .chain(
+                archive
+                    .behaviours
+                    .iter()
+                    .map(|other| behaviour.distance(other)),
+            )
+            .collect::<Vec<_>>();
+        distances.sort_by(f64::total_cmp);
+        distances.truncate(neighbours);
+        bots[index].score.novelty = distances.iter().sum::<f64>() / distances.len().max(1) as f64;
+    }
+
+    let mut by_novelty = (0..bots.len()).collect::<Vec<_>>();
+    by_novelty.sort_by(|&a, &b| bots[a].score.novelty.total_cmp(&bots[b].score.novelty));
+    let last = bots.len().saturating_sub(1).max(1) as f64;
+    for (novelty_rank, index) in by_novelty.into_iter().enumerate() {
+        let score_rank = index as f64 / last;
+        bots[index].score.hybrid_fitness =
+            novelty_weight * novelty_rank as f64 / last + (1.0 - novelty_weight) * score_rank;
+    }
+}
+
+/// Order of a bot by its hybrid fitness, greater is better
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct NoveltyRank(OrderedFloat);
+
+impl NoveltyRank {
+    pub fn of(bot: &Bot) -> NoveltyRank {
+        NoveltyRank(OrderedFloat(bot.score.hybrid_fitness))
+    }
+}
@@ -0,0 +1,136 @@
+use std::cmp::Reverse;
+
+use crate::ordered_float::OrderedFloat;
+use crate::Bot;
+
+pub const OBJECTIVES: [&str; 4] = ["wins", "units", "health", "damage"];
+
+/// Per game averages of the metrics, all of them maximised
+pub fn objectives(bot: &Bot) -> [f64; 4] {
+    let metrics = &bot.score.metrics;
+    let games = metrics.games.max(1) as f64;
+
+    [
+        metrics.wins as f64 / games,
+        metrics.unit_differential as f64 / games,
+        metrics.health_differential as f64 / games,
+        metrics.damage_dealt as f64 / games,
+    ]
+}
+
+fn dominates(a: &[f64; 4], b: &[f64; 4]) -> bool {
+    a.iter().zip(b).all(|(a, b)| a >= b) && a.iter().zip(b).any(|(a, b)| a > b)
+}
+
+/// Splits the points into fronts, the first one being the Pareto front and
+/// every later one only dominated by points of earlier fronts.
+fn non_dominated_sort(points: &[[f64; 4]]) -> Vec<Vec<usize>> {
+    let mut dominated_by = vec![0; points.len()];
+    let mut dominates_list = vec![vec![]; points.len()];
+    for a in 0..points.len() {
+        for b in 0..points.len() {
+            if dominates(&points[a], &points[b]) {
+                dominates_list[a].push(b);
+                dominated_by[b] += 1;
+            }
+        }
+    }
+
+    let mut fronts = vec![];
+    let mut front = (0..points.len())
+        .filter(|&a| dominated_by[a] == 0)
+        .collect::<Vec<_>>();
+    while !front.is_empty() {
+        let mut next = vec![];
+        for &a in &front {
+            for &b in &dominates_list[a] {
+                dominated_by[b] -= 1;
+                if dominated_by[b] == 0 {
+                    next.push(b);
+                }
+            }
+        }
+        fronts.push(front);
+        front = next;
+    }
+    fronts
+}
+
+/// How far apart a point's neighbours in its front are, summed over the
+/// objectives. The extremes of each objective get `f64::MAX` so they are
+/// always kept.
+fn crowding_distances(points: &[[f64; 4]], front: &[usize]) -> Vec<f64> {
+    let mut distances = vec![0.0; front.len()];
+    if front.len() <= 2 {
+        return vec![f64::MAX; front.len()];
+    }
+
+    for (objective, _) in OBJECTIVES.iter().enumerate() {
+        let mut order = (0..front.len()).collect::<Vec<_>>();
+        order.sort_by(|&a, &b| points[front[a]][objective].total_cmp(&points[front[b]][objective]));
+
+        let min = points[front[order[0]]][objective];
+        let max = points[front[order[order.len() - 1]]][objective];
+        distances[order[0]] = f64::MAX;
+        distances[order[order.len() - 1]] = f64::MAX;
+        if max == min {
+            continue;
+        }
+
+        for window in order.windows(3) {
+            let gap = points[front[window[2]]][objective] - points[front[window[0]]][objective];
+            let distance = &mut distances[window[1]];
+            if *distance < f64::MAX {
+                *distance += gap / (max - min);
+            }
+        }
+    }
+    distances
+}
+
+/// Stores every bot's front and crowding distance in its score.
+pub fn rank(bots: &mut [Bot]) {
+    let points = bots.iter().map(objectives).collect::<Vec<_>>();
+
+    for (index, front) in non_dominated_sort(&points).iter().enumerate() {
+        for (&bot, distance) in front.iter().zip(crowding_distances(&points, front)) {
+            bots[bot].score.pareto_front = index;
+            bots[bot].score.crowding_distance = distance;
+        }
+    }
+}
+
+/// NSGA-II order of a bot, greater is better: an earlier front, or the same
+/// front and less crowded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ParetoRank {
+    front: Reverse<usize>,
+    crowding_distance: OrderedFloat,
+}
+
+impl ParetoRank {
+    pub fn of(bot: &Bot) -> ParetoRank {
+        ParetoRank {
+            front: Reverse(bot.score.pareto_front),
+            crowding_distance: OrderedFloat(bot.score.crowding_distance),
+        }
+    }
+}
+
+/// Prints the bots of the Pareto front with their objectives
+pub fn report(bots: &[Bot], limit: usize) {
+    let front = bots
+        .iter()
+        .filter(|bot| bot.score.pareto_front == 0)
+        .collect::<Vec<_>>();
+    println!("\tPareto front:\t {} bots ({})", front.len(), OBJECTIVES.join(", "));
+
+    for bot in front.iter().take(limit) {
+        let values = objectives(bot)
+            .iter()
+            .map(|value| format!("{value:.2}"))
+            .collect::<Vec<_>>()
+            .join(", ");
+        println!("\t\t{}: {values}", bot.species);
+    }
+}
@@ -0,0 +1,162 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashSet};
+
+use logic::Team;
+use serde::{Deserialize, Serialize};
+
+use crate::family_tree::Species;
+use crate::ordered_float::OrderedFloat;
+use crate::Bot;
+
+/// Elo rating. Unlike `BotScore` it does not depend on which bracket a bot
+/// played in, and it is carried over between iterations for survivors.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(transparent)]
+pub struct Rating(pub f64);
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating(1500.0)
+    }
+}
+
+impl std::fmt::Display for Rating {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.0}", self.0)
+    }
+}
+
+impl Rating {
+    /// Expected points against `other`, 1 for a certain win
+    pub fn expected_score(self, other: Rating) -> f64 {
+        1.0 / (1.0 + 10f64.powf((other.0 - self.0) / 400.0))
+    }
+}
+
+/// Updates both ratings after a game, a draw counts as half a win.
+pub fn update(blue: &mut Rating, red: &mut Rating, winner: Option<Team>, k_factor: f64) {
+    let blue_score = match winner {
+        Some(Team::Blue) => 1.0,
+        Some(Team::Red) => 0.0,
+        None => 0.5,
+    };
+    let expected = blue.expected_score(*red);
+
+    blue.0 += k_factor * (blue_score - expected);
+    red.0 -= k_factor * (blue_score - expected);
+}
+
+/// Updates only `rating` after a game against an opponent whose rating is
+/// kept as it is, like a hall of fame champion. `score` is 1 for a win and
+/// 0.5 for a draw.
+pub fn update_against(rating: &mut Rating, opponent: Rating, score: f64, k_factor: f64) {
+    rating.0 += k_factor * (score - rating.expected_score(opponent));
+}
+
+/// A game that moves species ratings, see `SpeciesGames`
+#[derive(Debug, Clone, Copy)]
+enum SpeciesGame {
+    Between {
+        blue: Species,
+        red: Species,
+        winner: Option<Team>,
+    },
+    Against {
+        species: Species,
+        opponent: Rating,
+        score: f64,
+    },
+}
+
+/// The species rating updates of one island's evaluation. Islands are
+/// evaluated at once, so the games are collected and applied in island order
+/// afterwards, which gives the same ratings as evaluating them one by one.
+#[derive(Debug, Default)]
+pub struct SpeciesGames(Vec<SpeciesGame>);
+
+impl SpeciesGames {
+    /// See `SpeciesRatings::record`
+    pub fn record(&mut self, blue: Species, red: Species, winner: Option<Team>) {
+        self.0.push(SpeciesGame::Between { blue, red, winner });
+    }
+
+    /// See `SpeciesRatings::record_against`
+    pub fn record_against(&mut self, species: Species, opponent: Rating, score: f64) {
+        self.0.push(SpeciesGame::Against {
+            species,
+            opponent,
+            score,
+        });
+    }
+}
+
+/// Rating of every species in the population, updated by games between
+/// bots of different species and against hall of fame champions.
+#[derive(Serialize, Deserialize, Clone, Debug, Default)]
+#[serde(transparent)]
+pub struct SpeciesRatings(BTreeMap<Species, Rating>);
+
+impl SpeciesRatings {
+    pub fn get(&self, species: Species) -> Rating {
+        self.0.get(&species).copied().unwrap_or_default()
+    }
+
+    pub fn record(&mut self, blue: Species, red: Species, winner: Option<Team>, k_factor: f64) {
+        if blue == red {
+            return;
+        }
+
+        let mut blue_rating = self.get(blue);
+        let mut red_rating = self.get(red);
+        update(&mut blue_rating, &mut red_rating, winner, k_factor);
+        self.0.insert(blue, blue_rating);
+        self.0.insert(red, red_rating);
+    }
+
+    /// Game of a bot of `species` against an opponent outside of the
+    /// population, see `update_against`
+    pub fn record_against(
+        &mut self,
+        species: Species,
+        opponent: Rating,
+        score: f64,
+        k_factor: f64,
+    ) {
+        let mut rating = self.get(species);
+        update_against(&mut rating, opponent, score, k_factor);
+        self.0.insert(species, rating);
+    }
+
+    pub fn apply(&mut self, games: &SpeciesGames, k_factor: f64) {
+        for game in &games.0 {
+            match *game {
+                SpeciesGame::Between { blue, red, winner } => {
+                    self.record(blue, red, winner, k_factor)
+                }
+                SpeciesGame::Against {
+                    species,
+                    opponent,
+                    score,
+                } => self.record_against(species, opponent, score, k_factor),
+            }
+        }
+    }
+
+    /// Forgets species that died out.
+    pub fn retain_population(&mut self, bots: &[Bot]) {
+        let alive = bots.iter().map(|bot| bot.species).collect::<HashSet<_>>();
+        self.0.retain(|species, _| alive.contains(species));
+    }
+
+    /// The `count` highest rated species, best first
+    pub fn best(&self, count: usize) -> Vec<(Species, Rating)> {
+        let mut ratings = self
+            .0
+            .iter()
+            .map(|(&species, &rating)| (species, rating))
+            .collect::<Vec<_>>();
+        ratings.sort_by_key(|&(_, rating)| Reverse(OrderedFloat(rating.0)));
+        ratings.truncate(count);
+        ratings
+    }
+}
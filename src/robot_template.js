@@ -0,0 +1,75 @@
+function surroundingTiles(tile) {
+  return [
+    new Coords(tile.x, tile.y + 1),
+    new Coords(tile.x, tile.y - 1),
+    new Coords(tile.x - 1, tile.y),
+    new Coords(tile.x + 1, tile.y),
+  ]
+}
+
+function cornerTiles(tile) {
+  return [
+    new Coords(tile.x + 1, tile.y + 1),
+    new Coords(tile.x + 1, tile.y - 1),
+    new Coords(tile.x - 1, tile.y + 1),
+    new Coords(tile.x - 1, tile.y - 1),
+  ]
+}
+
+function count(tiles, predicate, state) {
+  return tiles.filter((tile) => predicate(tile, state)).length
+}
+
+function alliesAroundCorner(tile, state) {
+  return count(cornerTiles(tile), isFriendly, state)
+}
+
+function unsafeSurroundingTiles(tile, state) {
+  return count(surroundingTiles(tile), isEnemy, state)
+}
+
+function friendlySurroundingTiles(tile, state) {
+  return count(surroundingTiles(tile), isFriendly, state)
+}
+
+function isFriendly(tile, state) {
+  const obj = state.objByCoords(tile)
+  return obj != null && obj.team === state.ourTeam
+}
+
+function isEnemy(tile, state) {
+  const obj = state.objByCoords(tile)
+  return obj != null && obj.team === state.otherTeam
+}
+
+function minBy(items, key) {
+  let best = null
+  let bestKey = null
+  for (const item of items) {
+    const itemKey = key(item)
+    if (best === null || itemKey < bestKey) {
+      best = item
+      bestKey = itemKey
+    }
+  }
+  return best
+}
+
+function sameAction(a, b) {
+  return a.type === b.type && a.direction === b.direction
+}
+
+function robot(state, unit) {
+  const closestEnemy = minBy(state.objsByTeam(state.otherTeam), (e) =>
+    e.coords.walkingDistanceTo(unit.coords) * 10000 +
+    (10 - alliesAroundCorner(e.coords, state) - friendlySurroundingTiles(e.coords, state)) * 100 +
+    e.health
+  )
+  const closestAlly = minBy(
+    state.objsByTeam(unit.team).filter((a) => a.id !== unit.id),
+    (a) => a.coords.walkingDistanceTo(unit.coords) * 100 + a.health
+  )
+
+  const move = {}
+  return move
+}
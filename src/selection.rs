@@ -0,0 +1,161 @@
+use rand::distributions::{Distribution, WeightedIndex};
+use serde::{Deserialize, Serialize};
+
+/// How a bot is picked from a list ranked best first, used for both parent
+/// and survivor selection.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub enum Selection {
+    /// The best of `size` bots drawn uniformly
+    Tournament { size: usize },
+    /// Probability falls linearly with rank, the best bot is `pressure`
+    /// (between 1 and 2) times as likely as an average one
+    LinearRank { pressure: f64 },
+    /// Uniformly from the best `fraction` of the bots
+    Truncation { fraction: f64 },
+    /// Proportional to fitness, shifted so the worst bot still has a chance
+    Roulette,
+}
+
+impl Default for Selection {
+    fn default() -> Self {
+        Selection::Tournament { size: 2 }
+    }
+}
+
+impl Selection {
+    pub fn validate(&self) -> Result<(), String> {
+        match *self {
+            Selection::Tournament { size: 0 } => {
+                Err("tournament size should be at least 1".to_string())
+            }
+            Selection::LinearRank { pressure } if !(1.0..=2.0).contains(&pressure) => {
+                Err("linear rank pressure should be between 1 and 2".to_string())
+            }
+            Selection::Truncation { fraction } if !(fraction > 0.0 && fraction <= 1.0) => {
+                Err("truncation fraction should be above 0 and at most 1".to_string())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Index of the picked bot. `fitness` is ordered best first, only
+    /// roulette looks at the values.
+    pub fn pick<RNG: rand::Rng>(&self, fitness: &[f64], rng: &mut RNG) -> usize {
+        let length = fitness.len();
+        assert!(length > 0, "nothing to select from");
+
+        match *self {
+            Selection::Tournament { size } => (0..size)
+                .map(|_| rng.gen_range(0..length))
+                .min()
+                .unwrap(),
+            Selection::LinearRank { pressure } => {
+                if length == 1 {
+                    return 0;
+                }
+                let weights = (0..length).map(|rank| {
+                    pressure - 2.0 * (pressure - 1.0) * rank as f64 / (length - 1) as f64
+                });
+                weighted(weights, rng)
+            }
+            Selection::Truncation { fraction } => {
+                let kept = ((length as f64 * fraction).ceil() as usize).clamp(1, length);
+                rng.gen_range(0..kept)
+            }
+            Selection::Roulette => {
+                let min = fitness.iter().copied().fold(f64::INFINITY, f64::min);
+                let max = fitness.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                // the worst bot gets a small share instead of none
+                let floor = ((max - min) / length as f64).max(f64::EPSILON);
+                weighted(fitness.iter().map(|value| value - min + floor), rng)
+            }
+        }
+    }
+
+    /// Index of the bot to remove: the same strategy applied from the worst end.
+    pub fn pick_worst<RNG: rand::Rng>(&self, fitness: &[f64], rng: &mut RNG) -> usize {
+        let reversed = fitness.iter().rev().map(|value| -value).collect::<Vec<_>>();
+        fitness.len() - 1 - self.pick(&reversed, rng)
+    }
+}
+
+fn weighted<RNG: rand::Rng>(weights: impl IntoIterator<Item = f64>, rng: &mut RNG) -> usize {
+    // all weights are positive, so this can't fail
+    WeightedIndex::new(weights).unwrap().sample(rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    const STRATEGIES: [Selection; 4] = [
+        Selection::Tournament { size: 3 },
+        Selection::LinearRank { pressure: 1.8 },
+        Selection::Truncation { fraction: 0.25 },
+        Selection::Roulette,
+    ];
+
+    /// Fitness of `length` bots ranked best first
+    fn ranked_fitness(length: usize) -> Vec<f64> {
+        (0..length).map(|rank| (length - rank) as f64).collect()
+    }
+
+    fn mean_index(picks: impl Iterator<Item = usize>) -> f64 {
+        let picks = picks.collect::<Vec<_>>();
+        picks.iter().sum::<usize>() as f64 / picks.len() as f64
+    }
+
+    #[test]
+    fn picks_are_in_bounds() {
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        for selection in STRATEGIES {
+            for length in 1..10 {
+                let fitness = ranked_fitness(length);
+                for _ in 0..100 {
+                    assert!(selection.pick(&fitness, &mut rng) < length);
+                    assert!(selection.pick_worst(&fitness, &mut rng) < length);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pick_prefers_the_best_and_pick_worst_the_worst() {
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let fitness = ranked_fitness(20);
+        let middle = 9.5;
+
+        for selection in STRATEGIES {
+            let best = mean_index((0..2000).map(|_| selection.pick(&fitness, &mut rng)));
+            let worst = mean_index((0..2000).map(|_| selection.pick_worst(&fitness, &mut rng)));
+            assert!(best < middle - 2.0, "{selection:?} picked {best} on average");
+            assert!(worst > middle + 2.0, "{selection:?} removed {worst} on average");
+        }
+    }
+
+    #[test]
+    fn truncation_stays_within_the_fraction() {
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let selection = Selection::Truncation { fraction: 0.25 };
+        let fitness = ranked_fitness(8);
+
+        for _ in 0..200 {
+            assert!(selection.pick(&fitness, &mut rng) < 2);
+            assert!(selection.pick_worst(&fitness, &mut rng) >= 6);
+        }
+    }
+
+    #[test]
+    fn roulette_handles_equal_and_negative_fitness() {
+        let mut rng = ChaCha8Rng::seed_from_u64(4);
+        for fitness in [vec![0.0; 5], vec![-1.0, -2.0, -3.0]] {
+            for _ in 0..100 {
+                assert!(Selection::Roulette.pick(&fitness, &mut rng) < fitness.len());
+                assert!(Selection::Roulette.pick_worst(&fitness, &mut rng) < fitness.len());
+            }
+        }
+    }
+}
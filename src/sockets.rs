@@ -1,11 +1,17 @@
 
 
+use std::sync::Arc;
+
 use futures::SinkExt;
 use futures::StreamExt;
 use serde::Serialize;
 use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinSet;
+use tokio_tungstenite::tungstenite::Message;
 
+use crate::control::TrainingControl;
 use crate::family_tree::FamilyTree;
+use crate::island::IslandSummary;
 use crate::BotScore;
 use crate::{Bot, Species};
 
@@ -14,31 +20,49 @@ pub struct TrainingProgressAnnouncement {
     pub best_bot: Bot,
     pub species: FamilyTree,
     pub iteration_number: usize,
-    pub last_game: Vec<String>
+    pub last_game: Vec<String>,
+    pub islands: Vec<IslandSummary>,
 }
 
+/// Serves progress to websocket clients until `TrainingControl::shutdown`,
+/// then closes every connection. Clients can send `pause` and `resume`.
 pub async fn start_socket(
+    addr: String,
     receiver: tokio::sync::broadcast::Sender<TrainingProgressAnnouncement>,
+    control: Arc<TrainingControl>,
 ) -> Result<(), std::io::Error> {
-    let addr = std::env::args()
-        .nth(1)
-        .unwrap_or_else(|| "127.0.0.1:8080".to_string());
-
     // Create the event loop and TCP listener we'll accept connections on.
     let try_socket = TcpListener::bind(&addr).await;
     let listener = try_socket.expect("Failed to bind");
     println!("Listening on: {}", addr);
 
-    while let Ok((stream, _)) = listener.accept().await {
-        tokio::spawn(accept_connection(stream, receiver.subscribe()));
+    let mut shutdown = control.subscribe_shutdown();
+    let mut connections = JoinSet::new();
+    loop {
+        tokio::select! {
+            accepted = listener.accept() => match accepted {
+                Ok((stream, _)) => {
+                    connections.spawn(accept_connection(
+                        stream,
+                        receiver.subscribe(),
+                        control.clone(),
+                    ));
+                }
+                Err(_) => break,
+            },
+            _ = shutdown.changed() => break,
+        }
     }
 
+    while connections.join_next().await.is_some() {}
+
     Ok(())
 }
 
 async fn accept_connection(
     stream: TcpStream,
     mut listener: tokio::sync::broadcast::Receiver<TrainingProgressAnnouncement>,
+    control: Arc<TrainingControl>,
 ) {
     let addr = stream
         .peer_addr()
@@ -53,22 +77,40 @@ async fn accept_connection(
 
     let (mut write, mut read) = ws_stream.split();
 
-    tokio::spawn(async move {
-        while let Some(_) = read.next().await {
-            // ignore incoming messages
+    let mut shutdown = control.subscribe_shutdown();
+    let reader = tokio::spawn(async move {
+        while let Some(Ok(message)) = read.next().await {
+            if let Message::Text(command) = message {
+                match command.trim() {
+                    "pause" => control.set_paused(true),
+                    "resume" => control.set_paused(false),
+                    _ => (),
+                }
+            }
         }
     });
 
-    while let Ok(message) = listener.recv().await {
-        if let Err(e) = write
-            .send(tokio_tungstenite::tungstenite::Message::Text(
-                serde_json::to_string(&message).unwrap(),
-            ))
-            .await
-        {
-            eprint!("Connection closed, {e:?}");
-            break;
+    loop {
+        tokio::select! {
+            message = listener.recv() => {
+                let Ok(message) = message else {
+                    break;
+                };
+                if let Err(e) = write
+                    .send(Message::Text(serde_json::to_string(&message).unwrap()))
+                    .await
+                {
+                    eprint!("Connection closed, {e:?}");
+                    break;
+                }
+            }
+            _ = shutdown.changed() => {
+                let _ = write.send(Message::Close(None)).await;
+                let _ = write.close().await;
+                break;
+            }
         }
     }
+    reader.abort();
     // We should not forward messages other than text or binary.
 }
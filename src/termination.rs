@@ -0,0 +1,152 @@
+use std::time::{Duration, Instant};
+
+use crate::benchmark::{self, BenchmarkResult, Reference};
+use crate::cli::ExportFormat;
+use crate::commands::export_bot;
+use crate::config::Config;
+use crate::Bot;
+
+/// Why a run ended
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StopReason {
+    /// Ctrl-C or a stop from the websocket
+    Requested,
+    MaxIterations,
+    TimeBudget,
+    MaxGames,
+    TargetWinRate(f64),
+    Stagnation,
+}
+
+impl std::fmt::Display for StopReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StopReason::Requested => write!(f, "stop requested"),
+            StopReason::MaxIterations => write!(f, "reached max_iterations"),
+            StopReason::TimeBudget => write!(f, "used up time_budget_seconds"),
+            StopReason::MaxGames => write!(f, "reached max_games"),
+            StopReason::TargetWinRate(win_rate) => {
+                write!(f, "reached the target win rate with {win_rate:.2}")
+            }
+            StopReason::Stagnation => write!(f, "reached stagnation_limit"),
+        }
+    }
+}
+
+/// What the stop conditions look at, updated after every iteration.
+pub struct Progress {
+    started: Instant,
+    /// Iterations finished, including the ones before a resume
+    pub iterations: usize,
+    /// Games that ran, without cache hits and including the ones before a
+    /// resume
+    pub games_played: usize,
+    /// See `Adaptation::run_stagnant_iterations`
+    pub stagnant_iterations: usize,
+    /// Iteration and results of the last benchmark of this session
+    pub benchmark: Option<(usize, Vec<(Reference, BenchmarkResult)>)>,
+}
+
+impl Progress {
+    pub fn new(iterations: usize, games_played: usize, stagnant_iterations: usize) -> Progress {
+        Progress {
+            started: Instant::now(),
+            iterations,
+            games_played,
+            stagnant_iterations,
+            benchmark: None,
+        }
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// The first stop condition of `config` that is met, if any
+    pub fn check(&self, config: &Config) -> Option<StopReason> {
+        if config
+            .max_iterations
+            .is_some_and(|max| self.iterations >= max)
+        {
+            return Some(StopReason::MaxIterations);
+        }
+        if config
+            .time_budget_seconds
+            .is_some_and(|budget| self.elapsed() >= Duration::from_secs(budget))
+        {
+            return Some(StopReason::TimeBudget);
+        }
+        if config.max_games.is_some_and(|max| self.games_played >= max) {
+            return Some(StopReason::MaxGames);
+        }
+        if let (Some(target), Some((_, results))) = (config.target_win_rate, &self.benchmark) {
+            let win_rate = results
+                .iter()
+                .find(|(reference, _)| *reference == config.target_reference)
+                .map(|(_, result)| result.win_rate());
+            if let Some(win_rate) = win_rate.filter(|&win_rate| win_rate >= target) {
+                return Some(StopReason::TargetWinRate(win_rate));
+            }
+        }
+        if config
+            .stagnation_limit
+            .is_some_and(|limit| self.stagnant_iterations >= limit)
+        {
+            return Some(StopReason::Stagnation);
+        }
+        None
+    }
+}
+
+/// Prints a summary of the run, benchmarks the champion if the last
+/// iteration did not, and writes it next to the save file as
+/// `champion.py` and `champion.json`. The JSON genome can be passed to
+/// `play` and `export`.
+pub async fn finish(
+    config: &Config,
+    reason: StopReason,
+    champion: &Bot,
+    progress: &Progress,
+    hall_of_fame_size: usize,
+    seed: u64,
+) {
+    println!("Run ended: {reason}");
+    println!(
+        "\t{} iterations, {} games, {:?} in this session",
+        progress.iterations,
+        progress.games_played,
+        progress.elapsed()
+    );
+    println!(
+        "\tChampion:\t {} (generation {}), rating {}, fitness per game {:.2}, {} nodes",
+        champion.species,
+        champion.generation,
+        champion.rating,
+        champion.score.fitness_per_game(),
+        champion.logic.size()
+    );
+    println!("\tHall of fame:\t {hall_of_fame_size} champions");
+
+    let last_iteration = progress.iterations.saturating_sub(1);
+    match &progress.benchmark {
+        Some((iteration, results)) if *iteration == last_iteration => {
+            println!("\tBenchmark:\t {}", benchmark::summary(results))
+        }
+        _ if config.benchmark_games > 0 => {
+            let results = benchmark::benchmark(&champion.logic, config.benchmark_games, seed).await;
+            benchmark::record(config, last_iteration, &results);
+        }
+        _ => {}
+    }
+
+    for (extension, format) in [
+        ("champion.py", ExportFormat::Python),
+        ("champion.json", ExportFormat::Json),
+    ] {
+        let path = config.run_file(extension);
+        match std::fs::write(&path, export_bot(champion, format)) {
+            Ok(()) => println!("\tExported:\t {}", path.display()),
+            Err(e) => eprintln!("failed to write {}: {e}", path.display()),
+        }
+    }
+}
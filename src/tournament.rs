@@ -0,0 +1,321 @@
+use std::cmp::Reverse;
+use std::collections::HashSet;
+
+use futures::future::{BoxFuture, FutureExt};
+use logic::Team;
+use rand::seq::SliceRandom;
+use rand_chacha::ChaCha8Rng;
+
+use crate::config::{Config, TournamentKind};
+use crate::evaluation::play_games;
+use crate::fitness::{self, GameMetrics};
+use crate::game_cache::{CacheSession, GameCache};
+use crate::rating::{self, SpeciesGames};
+use crate::Bot;
+
+/// Everything a tournament needs besides the bots. Owns what it changes, so
+/// every island can be evaluated at the same time with its own.
+pub struct Evaluation<'a> {
+    pub cache: CacheSession<'a>,
+    pub species_games: SpeciesGames,
+    pub config: &'a Config,
+    pub seed: u64,
+    pub rng: ChaCha8Rng,
+    pub side_bias: SideBias,
+}
+
+impl Evaluation<'_> {
+    pub fn new<'a>(
+        cache: &'a GameCache,
+        config: &'a Config,
+        seed: u64,
+        rng: ChaCha8Rng,
+    ) -> Evaluation<'a> {
+        Evaluation {
+            cache: cache.session(),
+            species_games: SpeciesGames::default(),
+            config,
+            seed,
+            rng,
+            side_bias: SideBias::default(),
+        }
+    }
+}
+
+/// How often each colour won the games between members of the population.
+/// Far from even means the map or engine favours a side.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct SideBias {
+    pub blue_wins: usize,
+    pub red_wins: usize,
+    pub draws: usize,
+}
+
+impl SideBias {
+    pub fn add(&mut self, other: &SideBias) {
+        self.blue_wins += other.blue_wins;
+        self.red_wins += other.red_wins;
+        self.draws += other.draws;
+    }
+
+    pub fn record(&mut self, winner: Option<Team>) {
+        match winner {
+            Some(Team::Blue) => self.blue_wins += 1,
+            Some(Team::Red) => self.red_wins += 1,
+            None => self.draws += 1,
+        }
+    }
+}
+
+impl std::fmt::Display for SideBias {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let games = (self.blue_wins + self.red_wins + self.draws).max(1) as f64;
+        write!(
+            f,
+            "blue {:.1}%, red {:.1}%, draws {:.1}%",
+            100.0 * self.blue_wins as f64 / games,
+            100.0 * self.red_wins as f64 / games,
+            100.0 * self.draws as f64 / games
+        )
+    }
+}
+
+/// Decides who plays whom in an iteration. Implementations fill in the
+/// bots' scores and ratings and leave `bots` sorted from worst to best.
+#[async_trait::async_trait]
+pub trait Tournament: Send + Sync {
+    async fn run(&self, bots: &mut [Bot], evaluation: &mut Evaluation<'_>);
+}
+
+pub fn tournament(kind: TournamentKind) -> Box<dyn Tournament> {
+    match kind {
+        TournamentKind::Playoff => Box::new(Playoff),
+        TournamentKind::RoundRobin => Box::new(RoundRobin),
+        TournamentKind::Swiss => Box::new(Swiss),
+        TournamentKind::RandomOpponents => Box::new(RandomOpponents),
+    }
+}
+
+/// Mutable references to two different bots
+fn pair_mut(bots: &mut [Bot], first: usize, second: usize) -> (&mut Bot, &mut Bot) {
+    assert_ne!(first, second);
+    if first < second {
+        let (left, right) = bots.split_at_mut(second);
+        (&mut left[first], &mut right[0])
+    } else {
+        let (left, right) = bots.split_at_mut(first);
+        (&mut right[0], &mut left[second])
+    }
+}
+
+/// Plays the `(blue, red)` pairings and adds the results to the ratings, the
+/// points to `score.wins[round]` and the metrics to the fitness. With
+/// `symmetric_games` every pairing is also played with swapped colours.
+async fn play_round(
+    bots: &mut [Bot],
+    pairings: &[(usize, usize)],
+    round: usize,
+    evaluation: &mut Evaluation<'_>,
+) {
+    let mut pairings = pairings.to_vec();
+    if evaluation.config.symmetric_games {
+        let mirrored = pairings.iter().map(|&(blue, red)| (red, blue)).collect::<Vec<_>>();
+        pairings.extend(mirrored);
+    }
+
+    let results = play_games(bots, &pairings, &mut evaluation.cache, evaluation.seed).await;
+    let k_factor = evaluation.config.elo_k_factor;
+
+    for (&(bot_blue_index, bot_red_index), result) in pairings.iter().zip(results) {
+        evaluation.side_bias.record(result.winner);
+        let (blue, red) = pair_mut(bots, bot_blue_index, bot_red_index);
+        rating::update(&mut blue.rating, &mut red.rating, result.winner, k_factor);
+        evaluation
+            .species_games
+            .record(blue.species, red.species, result.winner);
+
+        for (index, team) in [(bot_blue_index, Team::Blue), (bot_red_index, Team::Red)] {
+            let metrics = GameMetrics::new(&result, team);
+            let score = &mut bots[index].score;
+            score.wins[round] += metrics.points();
+            score.add_game(&metrics, &evaluation.config.fitness);
+        }
+    }
+}
+
+/// The points against the hall of fame count like any other game.
+pub fn add_hall_of_fame_wins(bots: &mut [Bot]) {
+    for bot in bots.iter_mut() {
+        bot.score.wins[0] += bot.score.hall_of_fame_wins;
+    }
+}
+
+pub fn sort_by_fitness(bots: &mut [Bot]) {
+    bots.sort_by(|a, b| {
+        a.score
+            .fitness
+            .total_cmp(&b.score.fitness)
+            .then_with(|| a.score.cmp(&b.score))
+    });
+}
+
+/// Every bot plays its next `games_per_bot_per_round` neighbours, then the
+/// population is split into thirds by result and each third plays again,
+/// `playoff_rounds` deep. Each round's points are grouped into
+/// `playoff_buckets` groups and fitness decides within a group.
+pub struct Playoff;
+
+fn playoff_round<'a, 'b: 'a>(
+    bots: &'a mut [Bot],
+    iteration: usize,
+    evaluation: &'a mut Evaluation<'b>,
+) -> BoxFuture<'a, ()> {
+    let config = evaluation.config;
+    // every bot plays its neighbours as blue and is played by as many as red,
+    // the first round also counts the games against the hall of fame
+    let mut games = 2 * config.games_per_bot_per_round;
+    if iteration == 0 {
+        games += config.hall_of_fame_games;
+    }
+    if config.symmetric_games {
+        games *= 2;
+    }
+    let max_points = 2 * games;
+
+    async move {
+        if bots.is_empty() {
+            return;
+        }
+
+        let length = bots.len();
+        let pairings = (0..length)
+            .flat_map(|i| {
+                (1..=config.games_per_bot_per_round).map(move |offset| (i, (i + offset) % length))
+            })
+            .filter(|(bot_blue_index, bot_red_index)| bot_blue_index != bot_red_index)
+            .collect::<Vec<_>>();
+
+        play_round(bots, &pairings, iteration, evaluation).await;
+
+        for bot in bots.iter_mut() {
+            if iteration == 0 {
+                bot.score.wins[iteration] += bot.score.hall_of_fame_wins;
+            }
+            bot.score.wins[iteration] =
+                fitness::bucket(bot.score.wins[iteration], max_points, config.playoff_buckets);
+        }
+
+        bots.sort_by(|a, b| (a.score.wins[iteration], &a.score).cmp(&(b.score.wins[iteration], &b.score)));
+
+        if iteration < config.playoff_rounds - 1 {
+            // segment sizes are checked by `Config::validate`
+            let segment_size = bots.len() / 3;
+
+            playoff_round(&mut bots[0..segment_size], iteration + 1, evaluation).await;
+            playoff_round(&mut bots[segment_size..2 * segment_size], iteration + 1, evaluation).await;
+            playoff_round(&mut bots[2 * segment_size..], iteration + 1, evaluation).await;
+        }
+    }
+    .boxed()
+}
+
+#[async_trait::async_trait]
+impl Tournament for Playoff {
+    async fn run(&self, bots: &mut [Bot], evaluation: &mut Evaluation<'_>) {
+        playoff_round(bots, 0, evaluation).await;
+    }
+}
+
+/// Every bot plays every other bot once, colours alternate so each bot is
+/// blue in about half of its games. Quadratic in the population size.
+pub struct RoundRobin;
+
+#[async_trait::async_trait]
+impl Tournament for RoundRobin {
+    async fn run(&self, bots: &mut [Bot], evaluation: &mut Evaluation<'_>) {
+        let length = bots.len();
+        let pairings = (0..length)
+            .flat_map(|i| (i + 1..length).map(move |j| if (i + j) % 2 == 0 { (i, j) } else { (j, i) }))
+            .collect::<Vec<_>>();
+
+        add_hall_of_fame_wins(bots);
+        play_round(bots, &pairings, 0, evaluation).await;
+        sort_by_fitness(bots);
+    }
+}
+
+/// `games_per_bot_per_round` rounds, in each of which bots with similar
+/// points so far play each other, avoiding rematches where possible. With an
+/// odd number of bots the last unpaired bot gets a bye worth a draw.
+pub struct Swiss;
+
+#[async_trait::async_trait]
+impl Tournament for Swiss {
+    async fn run(&self, bots: &mut [Bot], evaluation: &mut Evaluation<'_>) {
+        let length = bots.len();
+        let mut played = HashSet::new();
+
+        add_hall_of_fame_wins(bots);
+        for round in 0..evaluation.config.games_per_bot_per_round {
+            // stable, so ties keep the shuffled order of the population
+            let mut standings = (0..length).collect::<Vec<_>>();
+            standings.sort_by_key(|&index| Reverse(bots[index].score.wins[0]));
+
+            let mut paired = vec![false; length];
+            let mut pairings = vec![];
+            for (position, &bot) in standings.iter().enumerate() {
+                if paired[bot] {
+                    continue;
+                }
+
+                let mut candidates = standings[position + 1..]
+                    .iter()
+                    .copied()
+                    .filter(|&other| !paired[other]);
+                let opponent = candidates
+                    .clone()
+                    .find(|&other| !played.contains(&(bot.min(other), bot.max(other))))
+                    .or_else(|| candidates.next());
+
+                match opponent {
+                    Some(other) => {
+                        paired[bot] = true;
+                        paired[other] = true;
+                        played.insert((bot.min(other), bot.max(other)));
+                        pairings.push(if round % 2 == 0 { (bot, other) } else { (other, bot) });
+                    }
+                    None => bots[bot].score.wins[0] += 1,
+                }
+            }
+
+            play_round(bots, &pairings, 0, evaluation).await;
+        }
+        sort_by_fitness(bots);
+    }
+}
+
+/// `games_per_bot_per_round` rounds against random opponents. Each round
+/// arranges the bots in a random cycle, so every bot plays once as blue and
+/// once as red per round.
+pub struct RandomOpponents;
+
+#[async_trait::async_trait]
+impl Tournament for RandomOpponents {
+    async fn run(&self, bots: &mut [Bot], evaluation: &mut Evaluation<'_>) {
+        let length = bots.len();
+        if length < 2 {
+            return;
+        }
+
+        let mut pairings = vec![];
+        for _round in 0..evaluation.config.games_per_bot_per_round {
+            let mut cycle = (0..length).collect::<Vec<_>>();
+            cycle.shuffle(&mut evaluation.rng);
+            pairings.extend((0..length).map(|i| (cycle[i], cycle[(i + 1) % length])));
+        }
+
+        add_hall_of_fame_wins(bots);
+        play_round(bots, &pairings, 0, evaluation).await;
+        sort_by_fitness(bots);
+    }
+}